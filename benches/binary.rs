@@ -0,0 +1,103 @@
+//! Compares reading transactions from csv with serde against reading the binary format.
+//! Run with `cargo bench --bench binary`.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+use toy_payments_engine::account::Transaction;
+use toy_payments_engine::binary::{self, decode, RECORD_SIZE};
+use toy_payments_engine::input::{self, InputFormat};
+
+/// How many transactions the files hold
+const TRANSACTIONS: u32 = 2_000_000;
+
+/// How many times each run is repeated, the fastest is reported
+const RUNS: usize = 5;
+
+/// Write a csv file with a mix of every transaction type
+fn generate(path: &str) {
+    let mut writer = BufWriter::new(File::create(path).expect("Failed to create the csv"));
+    writeln!(writer, "type, client, tx, amount").unwrap();
+    for tx in 1..=TRANSACTIONS {
+        let client = tx % 10_000;
+        let earlier = tx.saturating_sub(20_000).max(1);
+        match tx % 10 {
+            0..=4 => writeln!(writer, "deposit, {}, {}, 10.1234", client, tx),
+            5..=6 => writeln!(writer, "withdrawal, {}, {}, 4.5", client, tx),
+            7 => writeln!(writer, "dispute, {}, {},", client, earlier),
+            8 => writeln!(writer, "resolve, {}, {},", client, earlier),
+            _ => writeln!(writer, "chargeback, {}, {},", client, earlier),
+        }
+        .unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+/// The fastest of several runs
+fn fastest(mut run: impl FnMut() -> Duration) -> Duration {
+    (0..RUNS).map(|_| run()).min().unwrap_or_default()
+}
+
+fn main() {
+    let csv_path = env::temp_dir().join("bench_binary.csv");
+    let csv_path = csv_path.to_str().expect("Invalid temp path");
+    let binary_path = env::temp_dir().join("bench_binary.bin");
+    let binary_path = binary_path.to_str().expect("Invalid temp path");
+    generate(csv_path);
+    let mut reader = input::open(csv_path, InputFormat::Csv).expect("Failed to open the csv");
+    binary::convert(reader.as_mut(), binary_path).expect("Failed to convert the csv");
+
+    let csv = fastest(|| {
+        let start = Instant::now();
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(csv_path)
+            .unwrap();
+        let mut count = 0;
+        for transaction in reader.deserialize::<Transaction>() {
+            transaction.unwrap();
+            count += 1;
+        }
+        assert_eq!(count, TRANSACTIONS);
+        start.elapsed()
+    });
+    println!("csv deserialize: {:?}", csv);
+
+    let binary = fastest(|| {
+        let start = Instant::now();
+        let mut reader = input::open(binary_path, InputFormat::Binary).unwrap();
+        let mut count = 0;
+        while let Some(transaction) = reader.next_transaction() {
+            transaction.unwrap();
+            count += 1;
+        }
+        assert_eq!(count, TRANSACTIONS);
+        start.elapsed()
+    });
+    println!(
+        "binary reader: {:?} ({:.1}x)",
+        binary,
+        csv.as_secs_f64() / binary.as_secs_f64()
+    );
+
+    // the decoding alone, with the whole file already in memory
+    let bytes = fs::read(binary_path).unwrap();
+    let decoded = fastest(|| {
+        let start = Instant::now();
+        let mut count = 0;
+        for record in bytes.chunks_exact(RECORD_SIZE) {
+            decode(record.try_into().unwrap()).unwrap();
+            count += 1;
+        }
+        assert_eq!(count, TRANSACTIONS);
+        start.elapsed()
+    });
+    println!(
+        "binary decode in memory: {:?} ({:.1}x)",
+        decoded,
+        csv.as_secs_f64() / decoded.as_secs_f64()
+    );
+    let _ = fs::remove_file(csv_path);
+    let _ = fs::remove_file(binary_path);
+}
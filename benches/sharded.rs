@@ -0,0 +1,81 @@
+//! Compares processing on a single thread against the sharded processing.
+//! Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+use toy_payments_engine::account::{Transaction, TransactionType};
+use toy_payments_engine::account_manager::AccountManager;
+use toy_payments_engine::sharded::ShardedAccountManager;
+
+/// How many transactions to process in each run
+const TRANSACTIONS: u32 = 2_000_000;
+
+/// How many clients the transactions are spread over
+const CLIENTS: u32 = 10_000;
+
+/// How many times each run is repeated, the fastest is reported
+const RUNS: usize = 5;
+
+/// A mix of every transaction type, spread over many clients
+fn generate() -> Vec<Transaction> {
+    (1..=TRANSACTIONS)
+        .map(|tx| {
+            let client = (tx % CLIENTS) as u16;
+            // every transaction refers back to an earlier deposit of the same client
+            let earlier = tx.saturating_sub(CLIENTS * 2).max(1);
+            let (r#type, tx, amount) = match tx % 10 {
+                0..=4 => (TransactionType::Deposit, tx, Some(10.0)),
+                5..=6 => (TransactionType::Withdrawal, tx, Some(4.0)),
+                7 => (TransactionType::Dispute, earlier, None),
+                8 => (TransactionType::Resolve, earlier, None),
+                _ => (TransactionType::Chargeback, earlier, None),
+            };
+            Transaction {
+                r#type,
+                client,
+                tx,
+                amount,
+            }
+        })
+        .collect()
+}
+
+/// The fastest of several runs
+fn fastest(mut run: impl FnMut() -> Duration) -> Duration {
+    (0..RUNS).map(|_| run()).min().unwrap_or_default()
+}
+
+fn main() {
+    let transactions = generate();
+    let single = fastest(|| {
+        let transactions = transactions.clone();
+        let start = Instant::now();
+        let mut account_manager = AccountManager::default();
+        for transaction in transactions {
+            let _ = account_manager.process_transaction(transaction);
+        }
+        start.elapsed()
+    });
+    println!("single thread: {:?}", single);
+    let threads = std::thread::available_parallelism().map_or(4, |threads| threads.get());
+    for shards in [1, 2, 4, 8, 16]
+        .into_iter()
+        .filter(|shards| *shards <= threads)
+    {
+        let sharded = fastest(|| {
+            let transactions = transactions.clone();
+            let start = Instant::now();
+            let mut sharded = ShardedAccountManager::new(shards, false);
+            for transaction in transactions {
+                sharded.process_transaction(transaction);
+            }
+            sharded.finish();
+            start.elapsed()
+        });
+        println!(
+            "{} shards: {:?} ({:.2}x)",
+            shards,
+            sharded,
+            single.as_secs_f64() / sharded.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,8 @@
+/// Generate the gRPC service from proto/payments.proto, with a bundled protoc so none needs to be installed
+fn main() {
+    let protoc =
+        protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this platform");
+    std::env::set_var("PROTOC", protoc);
+    tonic_prost_build::compile_protos("proto/payments.proto")
+        .expect("Failed to compile the protobuf schema");
+}
@@ -1,10 +1,20 @@
+use crate::audit::{ViolationKind, TOLERANCE};
+use crate::event::{Event, EventKind};
+use crate::storage::{Storage, TransactionState};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::fmt;
 
 /// Round an f64 to 4 decimal places of precision.
 pub fn round(num: f64) -> f64 {
     let temp = (num * 10000.0) as i32;
-    return temp as f64 / 10000.0;
+    temp as f64 / 10000.0
+}
+
+/// Round an f64 to the nearest 4 decimal places, for amounts of any size.
+///
+/// Unlike `round` this does not go through an i32, so amounts above 214748.3647 keep their value.
+pub fn round_amount(num: f64) -> f64 {
+    (num * 10000.0).round() / 10000.0
 }
 
 /// The possible kinds of transactions that can be processed
@@ -18,8 +28,22 @@ pub enum TransactionType {
     Chargeback,
 }
 
+/// The same name as in the input csv
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 /// Contains all information relevant to a single transaction
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Transaction {
     pub r#type: TransactionType,
     pub client: u16,
@@ -59,40 +83,94 @@ impl Transaction {
     }
 }
 
+/// The reasons a transaction can be rejected instead of applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionError {
+    /// A deposit or withdrawal without a positive, finite amount
+    InvalidAmount,
+    /// The account is frozen and does not accept transactions
+    AccountFrozen,
+    /// A withdrawal larger than the available balance
+    InsufficientFunds,
+    /// The referenced transaction does not exist for this client
+    UnknownTransaction,
+    /// Only deposits can be disputed
+    NotADeposit,
+    /// The referenced transaction is already under dispute
+    AlreadyDisputed,
+    /// A resolve or chargeback referenced a transaction that is not under dispute
+    NotDisputed,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            TransactionError::InvalidAmount => "invalid amount",
+            TransactionError::AccountFrozen => "account frozen",
+            TransactionError::InsufficientFunds => "insufficient funds",
+            TransactionError::UnknownTransaction => "unknown transaction",
+            TransactionError::NotADeposit => "not a deposit",
+            TransactionError::AlreadyDisputed => "already disputed",
+            TransactionError::NotDisputed => "not disputed",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// What is on record for an account besides its balances, as kept in its Storage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Activity {
+    /// How many deposits and withdrawals are on record, a charged back deposit is no longer kept
+    pub transactions: usize,
+    /// How many deposits are under dispute
+    pub open_disputes: usize,
+    /// The highest id of the deposits and withdrawals on record
+    pub last_tx: Option<u32>,
+}
+
 /// Represents a single client's account information
-/// This should only contain transactions that apply to one client
+/// The transactions of the account are kept in a Storage, this only holds the balances
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
 pub struct Account {
-    transactions: HashMap<u32, Transaction>,
-    held_transactions: HashMap<u32, Transaction>,
     available_balance: f64,
+    held_balance: f64,
     frozen: bool,
     client_id: u16,
 }
 
-impl Default for Account {
-    fn default() -> Self {
+impl Account {
+    /// Returns a new client account
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - a unique u16 that identifies this client
+    pub fn new(client_id: u16) -> Self {
         Account {
-            transactions: HashMap::new(),
-            held_transactions: HashMap::new(),
             frozen: false,
             available_balance: 0.0,
-            client_id: 0,
+            held_balance: 0.0,
+            client_id,
         }
     }
-}
 
-impl Account {
-    /// Returns a new client account
+    /// Returns an existing client account, as it was kept in a Storage
     ///
     /// # Arguments
     ///
     /// * `client_id` - a unique u16 that identifies this client
-    pub fn new(client_id: u16) -> Self {
+    /// * `available_balance` - the amount available to the client
+    /// * `held_balance` - the amount held in dispute
+    /// * `frozen` - whether the account is frozen
+    pub fn with_balances(
+        client_id: u16,
+        available_balance: f64,
+        held_balance: f64,
+        frozen: bool,
+    ) -> Self {
         Account {
-            transactions: HashMap::new(),
-            held_transactions: HashMap::new(),
-            frozen: false,
-            available_balance: 0.0,
+            frozen,
+            available_balance,
+            held_balance,
             client_id,
         }
     }
@@ -121,7 +199,7 @@ impl Account {
         self.available_balance += amount;
     }
 
-    /// the transaction goes to the held hashmap,
+    /// the transaction becomes held,
     /// the available amount should decrease
     /// the held amount should increase
     /// the total should stay the same
@@ -129,49 +207,114 @@ impl Account {
     /// # Arguments
     ///
     /// * `disputed` - the Disputed type Transaction to be processed
-    fn dispute(&mut self, disputed: Transaction) {
+    /// * `storage` - where the disputed transaction is stored
+    fn dispute(
+        &mut self,
+        disputed: Transaction,
+        storage: &mut impl Storage,
+    ) -> Result<Event, TransactionError> {
         // We have never specified that you could only dispute Deposits, however,
         // we did define that a dispute will lower the available balance,
         // and that a resolve increasing the available balance, so it would not make sense for this to apply to a withdrawal
-        if let Some(transaction) = self.transactions.remove(&disputed.tx) {
-            if transaction.r#type == TransactionType::Deposit {
-                self.available_balance -= transaction.amount.unwrap_or(0.0);
-                self.held_transactions.insert(transaction.tx, transaction);
-            } else {
-                // Not a deposit. Why is it disputed?
-                // I am not sure how I feel about this, I could get a mutable reference and check the transaction, but I think this is more effecient.
-                self.transactions.insert(transaction.tx, transaction);
+        match storage.lookup_transaction(self.client_id, disputed.tx) {
+            Some((transaction, TransactionState::Settled)) => {
+                if transaction.r#type == TransactionType::Deposit {
+                    let amount = transaction.amount.unwrap_or(0.0);
+                    storage.move_transaction(
+                        self.client_id,
+                        disputed.tx,
+                        TransactionState::Settled,
+                        Some(TransactionState::Held),
+                    );
+                    self.available_balance -= amount;
+                    self.held_balance += amount;
+                    Ok(self.event(EventKind::Held, disputed.tx, amount))
+                } else {
+                    // Not a deposit. Why is it disputed?
+                    Err(TransactionError::NotADeposit)
+                }
             }
+            Some((_, TransactionState::Held)) => Err(TransactionError::AlreadyDisputed),
+            None => Err(TransactionError::UnknownTransaction),
         }
     }
 
-    /// the transaction goes to the held hashmap,
-    /// the available amount should decrease
-    /// the held amount should increase
+    /// the transaction is no longer held,
+    /// the available amount should increase
+    /// the held amount should decrease
     /// the total should stay the same
-    /// This action shall only apply to Deposits
     /// # Arguments
     ///
     /// * `resolved` - the Resolve type Transaction to be processed
-    fn resolve(&mut self, resolved: Transaction) {
-        if let Some(transaction) = self.held_transactions.remove(&resolved.tx) {
-            // Don't need to handle a check if it is a Deposit, a non-deposit should not end up in held transactions anyway
-            self.available_balance += transaction.amount.unwrap_or(0.0);
-            self.transactions.insert(transaction.tx, transaction);
+    /// * `storage` - where the disputed transaction is stored
+    fn resolve(
+        &mut self,
+        resolved: Transaction,
+        storage: &mut impl Storage,
+    ) -> Result<Event, TransactionError> {
+        // Don't need to handle a check if it is a Deposit, a non-deposit should not end up in held transactions anyway
+        match storage.move_transaction(
+            self.client_id,
+            resolved.tx,
+            TransactionState::Held,
+            Some(TransactionState::Settled),
+        ) {
+            Some(transaction) => {
+                let amount = transaction.amount.unwrap_or(0.0);
+                self.available_balance += amount;
+                self.held_balance -= amount;
+                Ok(self.event(EventKind::Released, resolved.tx, amount))
+            }
+            None => Err(self.not_held_error(resolved.tx, storage)),
         }
     }
 
-    /// the transaction goes to the held hashmap,
-    /// the available amount decreases
-    /// the held amount increases
-    /// the total remains unchanged
+    /// the held transaction is reversed and removed,
+    /// the held amount decreases
+    /// the total decreases
+    /// the account is frozen
     /// # Arguments
     ///
     /// * `charged_back` - the Chargeback type Transaction to be processed
-    fn chargeback(&mut self, charged_back: Transaction) {
+    /// * `storage` - where the disputed transaction is stored
+    fn chargeback(
+        &mut self,
+        charged_back: Transaction,
+        storage: &mut impl Storage,
+    ) -> Result<Event, TransactionError> {
         // Don't need to handle a check if it is a Deposit, a non-deposit should not end up in held transactions anyway
-        if let Some(_) = self.held_transactions.remove(&charged_back.tx) {
-            self.frozen = true;
+        match storage.move_transaction(
+            self.client_id,
+            charged_back.tx,
+            TransactionState::Held,
+            None,
+        ) {
+            Some(transaction) => {
+                let amount = transaction.amount.unwrap_or(0.0);
+                self.held_balance -= amount;
+                self.frozen = true;
+                Ok(self.event(EventKind::ChargedBack, charged_back.tx, amount))
+            }
+            None => Err(self.not_held_error(charged_back.tx, storage)),
+        }
+    }
+
+    /// Build an event describing a change that was applied to this account
+    fn event(&self, kind: EventKind, tx: u32, amount: f64) -> Event {
+        Event {
+            kind,
+            client: self.client_id,
+            tx,
+            amount,
+        }
+    }
+
+    /// Explain why a resolve or chargeback could not find its held transaction
+    fn not_held_error(&self, tx: u32, storage: &impl Storage) -> TransactionError {
+        if storage.lookup_transaction(self.client_id, tx).is_some() {
+            TransactionError::NotDisputed
+        } else {
+            TransactionError::UnknownTransaction
         }
     }
 
@@ -182,11 +325,7 @@ impl Account {
 
     /// Return the held amount - the total balance in dispute
     pub fn get_held_amount(&self) -> f64 {
-        let mut total = 0.0;
-        for value in self.held_transactions.values() {
-            total += value.amount.unwrap_or(0.0);
-        }
-        total
+        self.held_balance
     }
 
     /// Return the sum of the available balance and the funds held in dispute
@@ -208,47 +347,150 @@ impl Account {
     /// This is the main functionality of an account
     /// # Arguments
     ///
-    /// * `charged_back` - the Chargeback type Transaction to be processed
-    pub fn process_transaction(&mut self, transaction: Transaction) {
+    /// * `transaction` - the Transaction to be processed
+    /// * `storage` - where this account's transactions are stored
+    ///
+    /// Returns the change that was applied, or the reason the transaction was rejected
+    pub fn process_transaction(
+        &mut self,
+        transaction: Transaction,
+        storage: &mut impl Storage,
+    ) -> Result<Event, TransactionError> {
         match transaction.r#type {
             TransactionType::Deposit => {
-                self.deposit(transaction.amount.unwrap_or(0.0));
-                self.transactions.insert(transaction.tx, transaction);
+                let amount = transaction.amount.unwrap_or(0.0);
+                self.deposit(amount);
+                let event = self.event(EventKind::Deposited, transaction.tx, amount);
+                storage.insert_transaction(transaction);
+                Ok(event)
             }
             TransactionType::Withdrawal => {
-                if self.withdrawal(transaction.amount.unwrap_or(0.0)) {
-                    self.transactions.insert(transaction.tx, transaction);
+                let amount = transaction.amount.unwrap_or(0.0);
+                if self.withdrawal(amount) {
+                    let event = self.event(EventKind::Withdrew, transaction.tx, amount);
+                    storage.insert_transaction(transaction);
+                    Ok(event)
+                } else {
+                    Err(TransactionError::InsufficientFunds)
                 }
             }
-            TransactionType::Dispute => {
-                self.dispute(transaction);
+            TransactionType::Dispute => self.dispute(transaction, storage),
+            TransactionType::Resolve => self.resolve(transaction, storage),
+            TransactionType::Chargeback => self.chargeback(transaction, storage),
+        }
+    }
+
+    /// Apply a previously recorded event to this account.
+    /// Events describe changes that already passed validation, so nothing is checked here.
+    /// # Arguments
+    ///
+    /// * `event` - the Event to be applied
+    /// * `storage` - where this account's transactions are stored
+    pub fn apply_event(&mut self, event: &Event, storage: &mut impl Storage) {
+        let (client, tx) = (event.client, event.tx);
+        match event.kind {
+            EventKind::Deposited | EventKind::Withdrew => {
+                let r#type = if event.kind == EventKind::Deposited {
+                    self.available_balance += event.amount;
+                    TransactionType::Deposit
+                } else {
+                    self.available_balance -= event.amount;
+                    TransactionType::Withdrawal
+                };
+                storage.insert_transaction(Transaction {
+                    r#type,
+                    client,
+                    tx,
+                    amount: Some(event.amount),
+                });
+            }
+            EventKind::Held => {
+                let (from, to) = (TransactionState::Settled, TransactionState::Held);
+                storage.move_transaction(client, tx, from, Some(to));
+                self.available_balance -= event.amount;
+                self.held_balance += event.amount;
             }
-            TransactionType::Resolve => {
-                self.resolve(transaction);
+            EventKind::Released => {
+                let (from, to) = (TransactionState::Held, TransactionState::Settled);
+                storage.move_transaction(client, tx, from, Some(to));
+                self.available_balance += event.amount;
+                self.held_balance -= event.amount;
             }
-            TransactionType::Chargeback => {
-                self.chargeback(transaction);
+            EventKind::ChargedBack => {
+                storage.move_transaction(client, tx, TransactionState::Held, None);
+                self.held_balance -= event.amount;
+                self.frozen = true;
             }
         }
     }
 
+    /// Count the transactions on record for this account
+    pub fn activity(&self, storage: &impl Storage) -> Activity {
+        let transactions = storage.client_transactions(self.client_id);
+        Activity {
+            transactions: transactions.len(),
+            open_disputes: transactions
+                .iter()
+                .filter(|(_, state)| *state == TransactionState::Held)
+                .count(),
+            last_tx: transactions
+                .iter()
+                .map(|(transaction, _)| transaction.tx)
+                .max(),
+        }
+    }
+
+    /// Check the account invariants, returning every one that does not hold
+    /// * only deposits are held, and the held amount is the sum of them
+    /// * no transaction is both held and not held
+    pub fn check_invariants(&self, storage: &impl Storage) -> Vec<ViolationKind> {
+        let mut violations = Vec::new();
+        let held = self.get_held_amount();
+        let mut disputed = 0.0;
+        for transaction in storage.held_transactions(self.client_id) {
+            if transaction.r#type == TransactionType::Deposit {
+                disputed += transaction.amount.unwrap_or(0.0);
+            } else {
+                violations.push(ViolationKind::NonDepositHeld { tx: transaction.tx });
+            }
+            // the settled copy is the one found if the transaction is stored as both
+            let state = storage
+                .lookup_transaction(self.client_id, transaction.tx)
+                .map(|(_, state)| state);
+            if state == Some(TransactionState::Settled) {
+                violations.push(ViolationKind::DoubleBooked { tx: transaction.tx });
+            }
+        }
+        if (held - disputed).abs() > TOLERANCE {
+            violations.push(ViolationKind::HeldMismatch { held, disputed });
+        }
+        violations
+    }
+
     /// output the required csv fields for this account
     /// Returns the following fields: client, available, held, total, locked
     pub fn print(&self) {
-        println!(
+        println!("{}", self.row());
+    }
+
+    /// The csv row printed for this account, with the fields client, available, held, total, locked
+    pub fn row(&self) -> String {
+        format!(
             "{:?}, {:?}, {:?}, {:?}, {:?}",
             self.client_id,
             round(self.get_available_amount()),
             round(self.get_held_amount()),
             round(self.get_total_amount()),
             self.frozen
-        );
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::account::{Account, Transaction, TransactionType};
+    use crate::account::{Account, Transaction, TransactionError, TransactionType};
+    use crate::audit::ViolationKind;
+    use crate::storage::{MemoryStorage, Storage, TransactionState};
 
     // Test Transaction validation
     #[test]
@@ -333,13 +575,14 @@ mod tests {
     #[test]
     fn test_deposit() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
             tx: 1,
             amount: Some(100.0),
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
         assert_eq!(account.available_balance, 100.0);
         assert_eq!(account.get_total_amount(), 100.0);
@@ -348,6 +591,7 @@ mod tests {
     #[test]
     fn test_withdrawal() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
@@ -360,12 +604,12 @@ mod tests {
             tx: 2,
             amount: Some(50.0),
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
         assert_eq!(account.available_balance, 100.0);
         assert_eq!(account.get_total_amount(), 100.0);
         if account.withdrawal(trans2.amount.unwrap_or(0.0)) {
-            account.transactions.insert(1, trans2);
+            storage.insert_transaction(trans2);
         }
         assert_eq!(account.available_balance, 50.0);
         assert_eq!(account.get_total_amount(), 50.0);
@@ -375,6 +619,7 @@ mod tests {
     #[test]
     fn test_failed_withdrawal() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
@@ -387,12 +632,12 @@ mod tests {
             tx: 2,
             amount: Some(150.0),
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
         assert_eq!(account.available_balance, 100.0);
         assert_eq!(account.get_total_amount(), 100.0);
         if account.withdrawal(trans2.amount.unwrap_or(0.0)) {
-            account.transactions.insert(1, trans2);
+            storage.insert_transaction(trans2);
         }
         assert_eq!(account.available_balance, 100.0);
         assert_eq!(account.get_total_amount(), 100.0);
@@ -402,6 +647,7 @@ mod tests {
     #[test]
     fn test_dispute() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
@@ -414,9 +660,9 @@ mod tests {
             tx: 1,
             amount: Some(0.0),
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
-        account.dispute(trans2);
+        assert!(account.dispute(trans2, &mut storage).is_ok());
         assert_eq!(account.available_balance, 0.0);
         assert_eq!(account.get_total_amount(), 100.0);
         assert_eq!(account.get_held_amount(), 100.0);
@@ -425,6 +671,7 @@ mod tests {
     #[test]
     fn test_failed_dispute() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
@@ -437,9 +684,12 @@ mod tests {
             tx: 0, // we are referring to a transaction that does not exist!
             amount: None,
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
-        account.dispute(trans2);
+        assert_eq!(
+            account.dispute(trans2, &mut storage),
+            Err(TransactionError::UnknownTransaction)
+        );
         assert_eq!(account.available_balance, 100.0);
         assert_eq!(account.get_total_amount(), 100.0);
         assert_eq!(account.get_held_amount(), 0.0);
@@ -448,6 +698,7 @@ mod tests {
     #[test]
     fn test_resolve() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
@@ -466,11 +717,11 @@ mod tests {
             tx: 1,
             amount: None,
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
-        account.dispute(trans2);
+        assert!(account.dispute(trans2, &mut storage).is_ok());
         assert_eq!(account.get_held_amount(), 100.0);
-        account.resolve(trans3);
+        assert!(account.resolve(trans3, &mut storage).is_ok());
         assert_eq!(account.available_balance, 100.0);
         assert_eq!(account.get_total_amount(), 100.0);
         assert_eq!(account.get_held_amount(), 0.0);
@@ -479,6 +730,7 @@ mod tests {
     #[test]
     fn test_failed_resolve() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
@@ -497,19 +749,24 @@ mod tests {
             tx: 2, // we are referring to a transaction that does not exist!
             amount: None,
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
-        account.dispute(trans2);
+        assert!(account.dispute(trans2, &mut storage).is_ok());
         assert_eq!(account.get_held_amount(), 100.0);
-        account.resolve(trans3);
+        assert_eq!(
+            account.resolve(trans3, &mut storage),
+            Err(TransactionError::UnknownTransaction)
+        );
         assert_eq!(account.available_balance, 0.0);
         assert_eq!(account.get_total_amount(), 100.0);
         assert_eq!(account.get_held_amount(), 100.0);
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_chargeback() {
         let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
         let trans1 = Transaction {
             r#type: TransactionType::Deposit,
             client: 1,
@@ -528,16 +785,46 @@ mod tests {
             tx: 1,
             amount: None,
         };
-        account.transactions.insert(1, trans1);
+        storage.insert_transaction(trans1);
         account.deposit(100.0);
-        account.dispute(trans2);
+        assert!(account.dispute(trans2, &mut storage).is_ok());
         assert_eq!(account.available_balance, 0.0);
         assert_eq!(account.get_held_amount(), 100.0);
         // chargeback
-        account.chargeback(trans3);
+        assert!(account.chargeback(trans3, &mut storage).is_ok());
         assert_eq!(account.available_balance, 0.0);
         assert_eq!(account.get_total_amount(), 0.0);
         assert_eq!(account.get_held_amount(), 0.0);
         assert_eq!(account.frozen, true);
+        assert!(account.check_invariants(&storage).is_empty());
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut account = Account::new(1);
+        let mut storage = MemoryStorage::default();
+        let trans1 = Transaction {
+            r#type: TransactionType::Withdrawal,
+            client: 1,
+            tx: 1,
+            amount: Some(100.0),
+        };
+        // a withdrawal should never be held, and never both held and settled
+        storage.insert_transaction(trans1.clone());
+        storage.move_transaction(
+            1,
+            1,
+            TransactionState::Settled,
+            Some(TransactionState::Held),
+        );
+        storage.insert_transaction(trans1);
+        account.held_balance = 100.0;
+        let violations = account.check_invariants(&storage);
+        assert!(violations.contains(&ViolationKind::NonDepositHeld { tx: 1 }));
+        assert!(violations.contains(&ViolationKind::DoubleBooked { tx: 1 }));
+        assert!(violations.contains(&ViolationKind::HeldMismatch {
+            held: 100.0,
+            disputed: 0.0
+        }));
     }
 }
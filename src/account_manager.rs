@@ -1,33 +1,277 @@
-use crate::account::{Account, Transaction};
-use std::collections::HashMap;
+use crate::account::{Account, Activity, Transaction, TransactionError};
+use crate::audit::Violation;
+use crate::event::Event;
+use crate::observer::{notifications, Notification, Observer};
+use crate::snapshot::{Snapshot, SNAPSHOT_VERSION};
+use crate::storage::{MemoryStorage, Storage, TransactionState};
+use std::sync::mpsc;
 
-/// The Account Manager contains all of the accounts
-pub struct AccountManager {
-    accounts: HashMap<u16, Account>,
+/// The header of the accounts csv
+pub const ACCOUNTS_HEADER: &str = "client, available, held, total, locked";
+
+/// The Account Manager contains all of the accounts, kept in a Storage
+pub struct AccountManager<S: Storage = MemoryStorage> {
+    storage: S,
+    audit: bool,
+    violations: Vec<Violation>,
+    observers: Vec<Observer>,
 }
 
-impl Default for AccountManager {
+/// By default everything is kept in memory
+impl Default for AccountManager<MemoryStorage> {
     fn default() -> Self {
+        AccountManager::new(MemoryStorage::default())
+    }
+}
+
+/// Two account managers are equal when they hold identical accounts and transactions
+impl<S: Storage> PartialEq for AccountManager<S> {
+    fn eq(&self, other: &Self) -> bool {
+        let accounts = self.accounts();
+        accounts == other.accounts()
+            && accounts.iter().all(|account| {
+                self.sorted_transactions(account.get_id())
+                    == other.sorted_transactions(account.get_id())
+            })
+    }
+}
+
+impl<S: Storage> AccountManager<S> {
+    /// Create an account manager that keeps its accounts in the given storage
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - where to keep the accounts and transactions, it may already contain some
+    pub fn new(storage: S) -> Self {
         AccountManager {
-            accounts: HashMap::new(),
+            storage,
+            audit: false,
+            violations: Vec::new(),
+            observers: Vec::new(),
+        }
+    }
+
+    /// Every account, ordered by client
+    pub fn accounts(&self) -> Vec<Account> {
+        let mut accounts = self.storage.accounts();
+        accounts.sort_by_key(|account| account.get_id());
+        accounts
+    }
+
+    /// Every account along with what is on record for it, ordered by client
+    pub fn accounts_activity(&self) -> Vec<(Account, Activity)> {
+        self.accounts()
+            .into_iter()
+            .map(|account| (account, account.activity(&self.storage)))
+            .collect()
+    }
+
+    /// Every stored transaction of a client, ordered by transaction id
+    fn sorted_transactions(&self, client: u16) -> Vec<(Transaction, TransactionState)> {
+        let mut transactions = self.storage.client_transactions(client);
+        transactions.sort_by_key(|(transaction, state)| (transaction.tx, *state));
+        transactions
+    }
+
+    /// Replay previously applied events, in order.
+    /// Starting from an empty account manager this rebuilds the accounts without the original input.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - the applied events, in the order they were applied
+    pub fn apply_events<'a>(&mut self, events: impl IntoIterator<Item = &'a Event>) {
+        for event in events {
+            self.apply_event(event);
+        }
+    }
+
+    /// Restore the accounts and transactions from a snapshot of a previous run
+    pub fn load_snapshot(&mut self, snapshot: Snapshot) {
+        self.storage.begin();
+        for account in snapshot.accounts {
+            self.storage.put_account(account);
+        }
+        for (transaction, state) in snapshot.transactions {
+            let (client, tx) = (transaction.client, transaction.tx);
+            self.storage.insert_transaction(transaction);
+            if state == TransactionState::Held {
+                let settled = TransactionState::Settled;
+                self.storage
+                    .move_transaction(client, tx, settled, Some(state));
+            }
+        }
+        self.storage.commit();
+    }
+
+    /// Take over every account and transaction of another account manager, along with its recorded violations.
+    /// The two must not share any clients, as is the case for the shards of a ShardedAccountManager.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - the account manager to merge into this one
+    pub fn merge<T: Storage>(&mut self, other: AccountManager<T>) {
+        self.load_snapshot(other.snapshot());
+        self.violations.extend(other.violations);
+    }
+
+    /// Capture the full state of every account so it can be restored later
+    pub fn snapshot(&self) -> Snapshot {
+        // keep the file stable between runs with the same state
+        let accounts = self.accounts();
+        let transactions = accounts
+            .iter()
+            .flat_map(|account| self.sorted_transactions(account.get_id()))
+            .collect();
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            accounts,
+            transactions,
+            wal_seq: 0,
+            position: None,
+            event_rows: 0,
+        }
+    }
+
+    /// Apply a previously recorded event, creating the account if it does not exist
+    pub fn apply_event(&mut self, event: &Event) {
+        self.storage.begin();
+        let mut account = self
+            .storage
+            .get_account(event.client)
+            .unwrap_or_else(|| Account::new(event.client));
+        account.apply_event(event, &mut self.storage);
+        self.storage.put_account(account);
+        self.storage.record_event(event);
+        self.storage.commit();
+        self.notify(event, &account);
+    }
+
+    /// Call a function with every change applied to an account from now on, as soon as it is applied.
+    /// The callback runs on the thread applying the change, so it should be quick.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - called with every notification, in the order the changes were applied
+    pub fn subscribe(&mut self, callback: impl FnMut(&Notification) + Send + 'static) {
+        self.observers.push(Box::new(callback));
+    }
+
+    /// Receive every change applied to an account from now on over a channel
+    pub fn subscribe_channel(&mut self) -> mpsc::Receiver<Notification> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribe(move |notification| {
+            // a receiver that was dropped is no longer interested
+            let _ = sender.send(notification.clone());
+        });
+        receiver
+    }
+
+    /// Tell every subscriber about an applied event
+    fn notify(&mut self, event: &Event, account: &Account) {
+        if self.observers.is_empty() {
+            return;
+        }
+        for notification in notifications(event, account) {
+            for observer in self.observers.iter_mut() {
+                observer(&notification);
+            }
+        }
+    }
+
+    /// Turn self-audit mode on or off.
+    /// When on, the affected account's invariants are checked after every transaction
+    /// and any violations are recorded along with the offending transaction.
+    pub fn set_audit(&mut self, audit: bool) {
+        self.audit = audit;
+    }
+
+    /// The violations recorded while processing transactions in audit mode
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Check the invariants of every account, returning all violations found
+    /// This can be used at the end of processing whether or not audit mode is on.
+    pub fn audit(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for account in self.storage.accounts() {
+            for kind in account.check_invariants(&self.storage) {
+                violations.push(Violation {
+                    client: account.get_id(),
+                    kind,
+                    transaction: None,
+                });
+            }
+        }
+        violations
+    }
+
+    /// The violations recorded while processing, followed by those an audit of every account finds now
+    /// that were not already recorded for the same client, so a violation that lasts is reported once
+    pub fn audit_report(&self) -> Vec<Violation> {
+        let mut violations = self.violations.clone();
+        for found in self.audit() {
+            if !violations
+                .iter()
+                .any(|recorded| recorded.client == found.client && recorded.kind == found.kind)
+            {
+                violations.push(found);
+            }
+        }
+        violations
+    }
+
+    /// Check the account a transaction was just applied to and record any violations
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction that was just processed
+    fn audit_transaction(&mut self, transaction: Transaction) {
+        let account = match self.storage.get_account(transaction.client) {
+            Some(account) => account,
+            None => return,
+        };
+        for kind in account.check_invariants(&self.storage) {
+            self.violations.push(Violation {
+                client: transaction.client,
+                kind,
+                transaction: Some(transaction.clone()),
+            });
         }
     }
-}
 
-impl AccountManager {
     /// outputs csv format listing each account to stdout
     pub fn output_accounts(&self) {
-        println!("client, available, held, total, locked");
+        println!("{}", ACCOUNTS_HEADER);
         // print all account info
-        for client in self.accounts.values() {
+        for client in self.storage.accounts() {
             client.print();
         }
     }
 
+    /// Get a client's account, if any transaction has created it
+    pub fn find_account(&self, client_id: &u16) -> Option<Account> {
+        self.storage.get_account(*client_id)
+    }
+
+    /// Find a stored transaction of a client, along with whether it is held because of a dispute
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - the client the transaction belongs to
+    /// * `tx` - the transaction id
+    pub fn lookup_transaction(
+        &self,
+        client: u16,
+        tx: u32,
+    ) -> Option<(Transaction, TransactionState)> {
+        self.storage.lookup_transaction(client, tx)
+    }
+
     /// Get the available balance for a given client
     /// This is currently only used in the example
     pub fn _get_client_balance(&self, client_id: &u16) -> f64 {
-        if let Some(client) = self.accounts.get(client_id) {
+        if let Some(client) = self.storage.get_account(*client_id) {
             return client.get_available_amount();
         }
         0.0
@@ -40,60 +284,80 @@ impl AccountManager {
     ///
     /// * `transaction` - The transaction to be processed an affect an account's balance
     ///
-    /// // Doctests are not working for binaries, but you get an example anyway.
+    /// Returns the change that was applied, or the reason the transaction was rejected
+    ///
     /// # Example
     /// ```
-    /// use account_manager::AccountManager;
-    /// use account::Transaction;
+    /// use toy_payments_engine::account::{Transaction, TransactionType};
+    /// use toy_payments_engine::account_manager::AccountManager;
     ///
-    /// // process a single transaction and print the result
+    /// // process a single transaction and check the result
     /// let transaction = Transaction {
     ///     r#type: TransactionType::Deposit,
     ///     client: 1,
     ///     tx: 1,
     ///     amount: Some(100.0001),
     /// };
-    /// let account_manager = AccountManager::default();
-    /// account_manager.process_transaction(transaction);
-    /// assert_eq!(account_manager._get_client_balance(1), 1100.001);
-    ///
+    /// let mut account_manager = AccountManager::default();
+    /// account_manager.process_transaction(transaction).unwrap();
+    /// assert_eq!(account_manager._get_client_balance(&1), 100.0001);
     /// ```
-    pub fn process_transaction(&mut self, transaction: Transaction) {
+    pub fn process_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Event, TransactionError> {
         // check the transaction
-        if let Some(transaction) = transaction.validate() {
-            // find the account
-            match self.accounts.get_mut(&transaction.client) {
-                Some(account) => {
-                    // do not process any more transactions if the account is frozen
-                    if false == account.is_frozen() {
-                        account.process_transaction(transaction);
-                    }
-                }
-                None => {
-                    // Create the account:
-                    let mut new_account = Account::new(transaction.client);
-                    // then process the tx
-                    new_account.process_transaction(transaction);
-                    // save the account
-                    self.accounts.insert(new_account.get_id(), new_account);
-                }
-            }
+        let transaction = transaction
+            .validate()
+            .ok_or(TransactionError::InvalidAmount)?;
+        // everything the transaction changes is stored together
+        self.storage.begin();
+        // find the account, or create it if it does not exist
+        let existing = self.storage.get_account(transaction.client);
+        let mut account = existing.unwrap_or_else(|| Account::new(transaction.client));
+        // remember the transaction to audit it afterwards
+        let audited = Some(transaction.clone()).filter(|_| self.audit);
+        // do not process any more transactions if the account is frozen
+        let result = if !account.is_frozen() {
+            account.process_transaction(transaction, &mut self.storage)
+        } else {
+            Err(TransactionError::AccountFrozen)
+        };
+        // save the account, a new account is kept even if its first transaction was rejected
+        if result.is_ok() || existing.is_none() {
+            self.storage.put_account(account);
+        }
+        if let Ok(event) = &result {
+            self.storage.record_event(event);
+        }
+        self.storage.commit();
+        if let Ok(event) = &result {
+            self.notify(event, &account);
+        }
+        if let Some(transaction) = audited {
+            self.audit_transaction(transaction);
         }
+        result
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::account::{round, Account};
+    use crate::account::{round, Account, Transaction, TransactionError, TransactionType};
     use crate::account_manager::AccountManager;
+    use crate::input::temp_path;
+    use crate::observer::{Notification, NotificationKind};
+    use crate::snapshot::Snapshot;
+    use crate::storage::{Storage, TransactionState};
     use csv::{ReaderBuilder, Trim};
     use std::env;
+    use std::sync::{Arc, Mutex};
 
     // extra function for convenience
     impl AccountManager {
         fn get_account(&mut self, client: u16) -> Account {
-            self.accounts
-                .remove(&client)
+            self.storage
+                .get_account(client)
                 .expect("Failed to get account!")
         }
     }
@@ -110,7 +374,7 @@ mod tests {
             match result {
                 Ok(transaction) => {
                     println!("{:?}", transaction);
-                    account_manager.process_transaction(transaction);
+                    let _ = account_manager.process_transaction(transaction);
                 }
                 Err(error) => {
                     println!("Failed to deserialize a transaction: {:?}", error);
@@ -125,6 +389,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_single_client() {
         let mut args: Vec<String> = env::args().collect();
         if args.len() < 2 {
@@ -140,7 +405,7 @@ mod tests {
         for result in csv_reader.deserialize() {
             match result {
                 Ok(transaction) => {
-                    account_manager.process_transaction(transaction);
+                    let _ = account_manager.process_transaction(transaction);
                 }
                 Err(error) => {
                     println!("Failed to deserialize a transaction: {:?}", error);
@@ -152,4 +417,211 @@ mod tests {
         assert_eq!(account_manager.get_account(1).get_held_amount(), 1000.0);
         assert_eq!(account_manager.get_account(1).is_frozen(), true);
     }
+
+    #[test]
+    fn test_audit_bigger_file() {
+        let mut account_manager = AccountManager::default();
+        account_manager.set_audit(true);
+        let mut csv_reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path("testfiles/biggertestfile.csv")
+            .expect("Failed to read input file testfiles/biggertestfile.csv");
+        for result in csv_reader.deserialize() {
+            let _ = account_manager.process_transaction(result.expect("Failed to deserialize"));
+        }
+        assert!(account_manager.violations().is_empty());
+        assert!(account_manager.audit().is_empty());
+    }
+
+    #[test]
+    fn test_audit_report() {
+        let mut account_manager = AccountManager::default();
+        account_manager.set_audit(true);
+        let deposit = |tx| Transaction {
+            r#type: TransactionType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(10.0),
+        };
+        account_manager.process_transaction(deposit(1)).unwrap();
+        // hold the deposit without moving its amount, which breaks the held invariant from here on
+        account_manager.storage.move_transaction(
+            1,
+            1,
+            TransactionState::Settled,
+            Some(TransactionState::Held),
+        );
+        account_manager.process_transaction(deposit(2)).unwrap();
+        assert_eq!(account_manager.violations().len(), 1);
+        assert_eq!(account_manager.audit().len(), 1);
+        let report = account_manager.audit_report();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].transaction, Some(deposit(2)));
+    }
+
+    #[test]
+    fn test_replay_events() {
+        let mut account_manager = AccountManager::default();
+        let mut events = Vec::new();
+        for file in [
+            "testfiles/biggertestfile.csv",
+            "testfiles/singleclienttest.csv",
+        ] {
+            let mut csv_reader = ReaderBuilder::new()
+                .trim(Trim::All)
+                .from_path(file)
+                .expect("Failed to read input file");
+            for result in csv_reader.deserialize() {
+                if let Ok(event) =
+                    account_manager.process_transaction(result.expect("Failed to deserialize"))
+                {
+                    events.push(event);
+                }
+            }
+        }
+        let mut replayed = AccountManager::default();
+        replayed.apply_events(&events);
+        assert!(replayed == account_manager);
+        assert!(replayed.find_account(&88).expect("No account").is_frozen());
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut account_manager = AccountManager::default();
+        let mut csv_reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path("testfiles/biggertestfile.csv")
+            .expect("Failed to read input file testfiles/biggertestfile.csv");
+        for result in csv_reader.deserialize() {
+            let _ = account_manager.process_transaction(result.expect("Failed to deserialize"));
+        }
+        let path = &temp_path("test_snapshot_round_trip.json");
+        account_manager
+            .snapshot()
+            .save(path)
+            .expect("Failed to save snapshot");
+        let mut restored = AccountManager::default();
+        restored.load_snapshot(Snapshot::load(path).expect("Failed to load snapshot"));
+        assert!(restored == account_manager);
+        // disputes of deposits from before the snapshot still work
+        let dispute = Transaction {
+            r#type: TransactionType::Dispute,
+            client: 1,
+            tx: 12,
+            amount: None,
+        };
+        assert!(restored.process_transaction(dispute).is_ok());
+        assert_eq!(restored.get_account(1).get_held_amount(), 100.0001);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let mut account_manager = AccountManager::default();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let callback = received.clone();
+        account_manager.subscribe(move |notification| {
+            callback.lock().unwrap().push(notification.kind);
+        });
+        let channel = account_manager.subscribe_channel();
+        for (r#type, tx, amount) in [
+            (TransactionType::Deposit, 1, Some(10.0)),
+            (TransactionType::Withdrawal, 2, Some(50.0)),
+            (TransactionType::Dispute, 1, None),
+            (TransactionType::Resolve, 1, None),
+            (TransactionType::Dispute, 1, None),
+            (TransactionType::Chargeback, 1, None),
+        ] {
+            let _ = account_manager.process_transaction(Transaction {
+                r#type,
+                client: 3,
+                tx,
+                amount,
+            });
+        }
+        // the rejected withdrawal does not notify anyone
+        let expected = vec![
+            NotificationKind::BalanceChanged,
+            NotificationKind::BalanceChanged,
+            NotificationKind::Disputed,
+            NotificationKind::BalanceChanged,
+            NotificationKind::Resolved,
+            NotificationKind::BalanceChanged,
+            NotificationKind::Disputed,
+            NotificationKind::BalanceChanged,
+            NotificationKind::ChargedBack,
+            NotificationKind::Locked,
+        ];
+        assert_eq!(*received.lock().unwrap(), expected);
+        let notifications: Vec<Notification> = channel.try_iter().collect();
+        assert_eq!(
+            notifications.iter().map(|n| n.kind).collect::<Vec<_>>(),
+            expected
+        );
+        let disputed = &notifications[2];
+        assert_eq!(
+            (disputed.client, disputed.tx, disputed.amount),
+            (3, 1, 10.0)
+        );
+        assert_eq!((disputed.available, disputed.held), (0.0, 10.0));
+        assert!(notifications[9].locked);
+    }
+
+    #[test]
+    fn test_rejection_reasons() {
+        let mut account_manager = AccountManager::default();
+        let transaction = |r#type, tx, amount| Transaction {
+            r#type,
+            client: 1,
+            tx,
+            amount,
+        };
+        assert_eq!(
+            account_manager.process_transaction(transaction(TransactionType::Deposit, 1, None)),
+            Err(TransactionError::InvalidAmount)
+        );
+        assert!(account_manager
+            .process_transaction(transaction(TransactionType::Deposit, 1, Some(10.0)))
+            .is_ok());
+        assert_eq!(
+            account_manager.process_transaction(transaction(
+                TransactionType::Withdrawal,
+                2,
+                Some(20.0)
+            )),
+            Err(TransactionError::InsufficientFunds)
+        );
+        assert!(account_manager
+            .process_transaction(transaction(TransactionType::Withdrawal, 3, Some(5.0)))
+            .is_ok());
+        assert_eq!(
+            account_manager.process_transaction(transaction(TransactionType::Dispute, 3, None)),
+            Err(TransactionError::NotADeposit)
+        );
+        assert_eq!(
+            account_manager.process_transaction(transaction(TransactionType::Resolve, 1, None)),
+            Err(TransactionError::NotDisputed)
+        );
+        assert!(account_manager
+            .process_transaction(transaction(TransactionType::Dispute, 1, None))
+            .is_ok());
+        assert_eq!(
+            account_manager.process_transaction(transaction(TransactionType::Dispute, 1, None)),
+            Err(TransactionError::AlreadyDisputed)
+        );
+        assert_eq!(
+            account_manager.process_transaction(transaction(TransactionType::Chargeback, 9, None)),
+            Err(TransactionError::UnknownTransaction)
+        );
+        assert!(account_manager
+            .process_transaction(transaction(TransactionType::Chargeback, 1, None))
+            .is_ok());
+        assert_eq!(
+            account_manager.process_transaction(transaction(
+                TransactionType::Deposit,
+                4,
+                Some(1.0)
+            )),
+            Err(TransactionError::AccountFrozen)
+        );
+    }
 }
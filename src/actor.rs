@@ -0,0 +1,196 @@
+use crate::account::{Account, Transaction, TransactionError};
+use crate::account_manager::AccountManager;
+use crate::event::Event;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+/// How many transactions can be waiting for an account before senders have to wait
+const QUEUED_TRANSACTIONS: usize = 64;
+
+/// What an account task can be asked to do
+enum Command {
+    /// Process a transaction and reply with its outcome
+    Process(
+        Transaction,
+        oneshot::Sender<Result<Event, TransactionError>>,
+    ),
+    /// Reply with the client's account, if it exists
+    Account(u16, oneshot::Sender<Option<Account>>),
+    /// Reply with the account and its transactions
+    Snapshot(oneshot::Sender<AccountManager>),
+}
+
+/// An async account manager where every account is driven by its own task.
+/// Transactions are routed to the task of their client over a channel,
+/// so each account only ever handles one transaction at a time while different accounts run concurrently.
+///
+/// The handle is cheap to clone, and any number of input streams can feed the same engine at once.
+/// Transactions sent by one stream are applied in the order it sent them,
+/// as long as the stream waits for each outcome before sending the next.
+#[derive(Clone, Default)]
+pub struct AsyncAccountManager {
+    accounts: Arc<Mutex<HashMap<u16, mpsc::Sender<Command>>>>,
+}
+
+/// Run the task of a single account until every handle is dropped.
+/// The account and its transactions are kept in an account manager of their own.
+async fn run_account(mut receiver: mpsc::Receiver<Command>) {
+    let mut account_manager = AccountManager::default();
+    while let Some(command) = receiver.recv().await {
+        match command {
+            Command::Process(transaction, reply) => {
+                // the sender may have stopped waiting, the transaction is applied either way
+                let _ = reply.send(account_manager.process_transaction(transaction));
+            }
+            Command::Account(client, reply) => {
+                let _ = reply.send(account_manager.find_account(&client));
+            }
+            Command::Snapshot(reply) => {
+                let mut copy = AccountManager::default();
+                copy.load_snapshot(account_manager.snapshot());
+                let _ = reply.send(copy);
+            }
+        }
+    }
+}
+
+impl AsyncAccountManager {
+    /// Create an engine with no accounts, account tasks are spawned as clients are seen
+    pub fn new() -> Self {
+        AsyncAccountManager::default()
+    }
+
+    /// The channel to a client's account task, spawning the task if it does not exist.
+    /// Must be called from within a tokio runtime.
+    fn sender(&self, client: u16) -> mpsc::Sender<Command> {
+        let mut accounts = self.accounts.lock().expect("The account list was poisoned");
+        accounts
+            .entry(client)
+            .or_insert_with(|| {
+                let (sender, receiver) = mpsc::channel(QUEUED_TRANSACTIONS);
+                tokio::spawn(run_account(receiver));
+                sender
+            })
+            .clone()
+    }
+
+    /// Every account task spawned so far
+    fn senders(&self) -> Vec<mpsc::Sender<Command>> {
+        let accounts = self.accounts.lock().expect("The account list was poisoned");
+        accounts.values().cloned().collect()
+    }
+
+    /// Process a transaction on its client's account task, and wait for the outcome
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to be processed
+    ///
+    /// Returns the change that was applied, or the reason the transaction was rejected
+    pub async fn process_transaction(
+        &self,
+        transaction: Transaction,
+    ) -> Result<Event, TransactionError> {
+        let (reply, outcome) = oneshot::channel();
+        self.sender(transaction.client)
+            .send(Command::Process(transaction, reply))
+            .await
+            .expect("An account task stopped unexpectedly");
+        outcome.await.expect("An account task stopped unexpectedly")
+    }
+
+    /// Get a client's account, once every transaction sent to it so far has been processed
+    pub async fn find_account(&self, client: u16) -> Option<Account> {
+        let sender = {
+            let accounts = self.accounts.lock().expect("The account list was poisoned");
+            accounts.get(&client).cloned()
+        }?;
+        // only the account is sent back, its transactions stay with the task
+        let (reply, account) = oneshot::channel();
+        sender
+            .send(Command::Account(client, reply))
+            .await
+            .expect("An account task stopped unexpectedly");
+        account.await.expect("An account task stopped unexpectedly")
+    }
+
+    /// Copy an account task's account manager
+    async fn copy(sender: mpsc::Sender<Command>) -> AccountManager {
+        let (reply, copy) = oneshot::channel();
+        sender
+            .send(Command::Snapshot(reply))
+            .await
+            .expect("An account task stopped unexpectedly");
+        copy.await.expect("An account task stopped unexpectedly")
+    }
+
+    /// Collect every account and its transactions into a single account manager.
+    /// Transactions still being processed by other handles may or may not be included.
+    pub async fn account_manager(&self) -> AccountManager {
+        let mut merged = AccountManager::default();
+        for sender in self.senders() {
+            merged.merge(Self::copy(sender).await);
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionType};
+    use crate::account_manager::AccountManager;
+    use crate::actor::AsyncAccountManager;
+    use csv::{ReaderBuilder, Trim};
+
+    #[tokio::test]
+    async fn test_matches_account_manager() {
+        let mut account_manager = AccountManager::default();
+        let engine = AsyncAccountManager::new();
+        let mut csv_reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path("testfiles/biggertestfile.csv")
+            .expect("Failed to read input file testfiles/biggertestfile.csv");
+        for result in csv_reader.deserialize::<Transaction>() {
+            let transaction = result.expect("Failed to deserialize");
+            assert_eq!(
+                account_manager.process_transaction(transaction.clone()),
+                engine.process_transaction(transaction).await
+            );
+        }
+        assert!(engine.account_manager().await == account_manager);
+        assert_eq!(
+            engine.find_account(44).await,
+            account_manager.find_account(&44)
+        );
+        assert_eq!(engine.find_account(999).await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_streams() {
+        let engine = AsyncAccountManager::new();
+        let mut streams = Vec::new();
+        for stream in 0..8u32 {
+            let engine = engine.clone();
+            streams.push(tokio::spawn(async move {
+                for i in 0..500u32 {
+                    let deposit = Transaction {
+                        r#type: TransactionType::Deposit,
+                        client: (i % 10) as u16,
+                        tx: stream * 1000 + i,
+                        amount: Some(1.0),
+                    };
+                    assert!(engine.process_transaction(deposit).await.is_ok());
+                }
+            }));
+        }
+        for stream in streams {
+            stream.await.expect("A stream panicked");
+        }
+        // every deposit of every stream was applied exactly once
+        for client in 0..10 {
+            let account = engine.find_account(client).await.expect("No account");
+            assert_eq!(account.get_available_amount(), 400.0);
+        }
+    }
+}
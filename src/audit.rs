@@ -0,0 +1,54 @@
+use crate::account::Transaction;
+use std::fmt;
+
+/// Two floats are considered equal for auditing if they differ by less than this
+pub const TOLERANCE: f64 = 0.00001;
+
+/// The kinds of account invariants that can be broken
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+    /// The held amount is not the sum of the disputed deposits
+    HeldMismatch { held: f64, disputed: f64 },
+    /// A non-deposit transaction ended up in the held transactions
+    NonDepositHeld { tx: u32 },
+    /// A transaction is stored as both held and not held
+    DoubleBooked { tx: u32 },
+}
+
+/// A broken invariant found while auditing an account
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub client: u16,
+    pub kind: ViolationKind,
+    /// The transaction that was processed right before the violation was found.
+    /// This is None when the violation was found by an audit after processing.
+    pub transaction: Option<Transaction>,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ViolationKind::HeldMismatch { held, disputed } => write!(
+                f,
+                "held {} does not equal the sum of disputed deposits {}",
+                held, disputed
+            ),
+            ViolationKind::NonDepositHeld { tx } => {
+                write!(f, "transaction {} is held but is not a deposit", tx)
+            }
+            ViolationKind::DoubleBooked { tx } => {
+                write!(f, "transaction {} is both held and not held", tx)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "client {}: {}", self.client, self.kind)?;
+        if let Some(transaction) = &self.transaction {
+            write!(f, " after {:?}", transaction)?;
+        }
+        Ok(())
+    }
+}
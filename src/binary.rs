@@ -0,0 +1,251 @@
+use crate::account::{Transaction, TransactionType};
+use crate::compression::{Compressed, Decompressed};
+use crate::input::{InputError, TransactionReader};
+use crate::wal::InputPosition;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// The size of a record: a type byte, a u16 client, a u32 tx and an i64 amount, all little-endian
+pub const RECORD_SIZE: usize = 15;
+
+/// Amounts are stored in ten-thousandths, the precision of the accounts csv
+const SCALE: f64 = 10_000.0;
+
+/// The amount of disputes, resolves and chargebacks, which have none
+const NO_AMOUNT: i64 = i64::MIN;
+
+/// How many records are read from the file at a time
+const BUFFERED_RECORDS: usize = 64 * 1024;
+
+/// Encode a transaction as a binary record.
+/// The amount is rounded to four decimal places, the same precision as the accounts csv.
+///
+/// # Arguments
+///
+/// * `transaction` - the transaction to encode, its amount must be finite
+pub fn encode(transaction: &Transaction) -> Result<[u8; RECORD_SIZE], String> {
+    let r#type: u8 = match transaction.r#type {
+        TransactionType::Deposit => 0,
+        TransactionType::Withdrawal => 1,
+        TransactionType::Dispute => 2,
+        TransactionType::Resolve => 3,
+        TransactionType::Chargeback => 4,
+    };
+    let amount = match transaction.amount {
+        None => NO_AMOUNT,
+        Some(amount) => {
+            let scaled = (amount * SCALE).round();
+            // the bounds of i64 as a f64, NO_AMOUNT is kept for missing amounts
+            if !scaled.is_finite() || scaled <= i64::MIN as f64 || scaled >= i64::MAX as f64 {
+                return Err(format!("the amount {} does not fit a record", amount));
+            }
+            scaled as i64
+        }
+    };
+    let mut record = [0; RECORD_SIZE];
+    record[0] = r#type;
+    record[1..3].copy_from_slice(&transaction.client.to_le_bytes());
+    record[3..7].copy_from_slice(&transaction.tx.to_le_bytes());
+    record[7..15].copy_from_slice(&amount.to_le_bytes());
+    Ok(record)
+}
+
+/// Decode a binary record, straight from the bytes it was read into
+///
+/// # Arguments
+///
+/// * `record` - the record
+pub fn decode(record: &[u8; RECORD_SIZE]) -> Result<Transaction, String> {
+    let r#type = match record[0] {
+        0 => TransactionType::Deposit,
+        1 => TransactionType::Withdrawal,
+        2 => TransactionType::Dispute,
+        3 => TransactionType::Resolve,
+        4 => TransactionType::Chargeback,
+        other => return Err(format!("unknown transaction type {}", other)),
+    };
+    let client = u16::from_le_bytes([record[1], record[2]]);
+    let tx = u32::from_le_bytes([record[3], record[4], record[5], record[6]]);
+    let amount = i64::from_le_bytes([
+        record[7], record[8], record[9], record[10], record[11], record[12], record[13], record[14],
+    ]);
+    Ok(Transaction {
+        r#type,
+        client,
+        tx,
+        amount: (amount != NO_AMOUNT).then(|| amount as f64 / SCALE),
+    })
+}
+
+/// Write every transaction of a reader to a file in the binary format, such as to convert a csv file
+/// Returns how many transactions were written
+///
+/// # Arguments
+///
+/// * `reader` - the transactions to convert
+/// * `path` - the binary file to create, compressed for .gz and .zst
+pub fn convert(reader: &mut dyn TransactionReader, path: &str) -> Result<u64, InputError> {
+    let mut writer = Compressed::create(path)?;
+    let mut written = 0;
+    while let Some(result) = reader.next_transaction() {
+        let (line, transaction) = result?;
+        let record = encode(&transaction).map_err(|message| InputError::Record {
+            record: line,
+            message,
+        })?;
+        writer.write_all(&record)?;
+        written += 1;
+    }
+    writer.finish()?;
+    Ok(written)
+}
+
+/// Reads transactions from a binary file of fixed-size records.
+/// Records are decoded directly from a large read buffer, only a record split across two reads is copied.
+/// The line of a transaction is its record number starting from 1.
+pub struct BinaryReader {
+    file: Decompressed,
+    buffer: Vec<u8>,
+    /// The unread bytes are buffer[start..end]
+    start: usize,
+    end: usize,
+    position: InputPosition,
+}
+
+impl BinaryReader {
+    /// Open a binary file, which may be compressed
+    pub fn open(path: &str) -> Result<Self, InputError> {
+        Ok(BinaryReader {
+            file: Decompressed::open(path)?,
+            buffer: vec![0; BUFFERED_RECORDS * RECORD_SIZE],
+            start: 0,
+            end: 0,
+            position: InputPosition {
+                byte: 0,
+                line: 1,
+                record: 0,
+            },
+        })
+    }
+
+    /// Refill the buffer, keeping any partial record at its front
+    /// Returns false at the end of the file
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buffer.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        while self.end < RECORD_SIZE {
+            match self.file.read(&mut self.buffer[self.end..])? {
+                0 => return Ok(false),
+                read => self.end += read,
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl TransactionReader for BinaryReader {
+    fn next_transaction(&mut self) -> Option<Result<(u64, Transaction), InputError>> {
+        if self.end - self.start < RECORD_SIZE {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) if self.end == 0 => return None,
+                Ok(false) => {
+                    return Some(Err(InputError::Record {
+                        record: self.position.line,
+                        message: format!(
+                            "the file ends {} bytes into a record",
+                            self.end - self.start
+                        ),
+                    }))
+                }
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+        let record: &[u8; RECORD_SIZE] = self.buffer[self.start..self.start + RECORD_SIZE]
+            .try_into()
+            .expect("The slice is one record long");
+        let record_number = self.position.line;
+        self.start += RECORD_SIZE;
+        self.position.byte += RECORD_SIZE as u64;
+        self.position.line += 1;
+        self.position.record += 1;
+        Some(
+            decode(record)
+                .map(|transaction| (record_number, transaction))
+                .map_err(|message| InputError::Record {
+                    record: record_number,
+                    message,
+                }),
+        )
+    }
+
+    fn position(&self) -> InputPosition {
+        self.position
+    }
+
+    fn seek(&mut self, position: InputPosition) -> Result<(), InputError> {
+        self.file.seek(SeekFrom::Start(position.byte))?;
+        self.start = 0;
+        self.end = 0;
+        self.position = position;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionType};
+    use crate::binary::{convert, decode, encode, RECORD_SIZE};
+    use crate::input::{check_matches_csv, open, read_all, temp_path, InputError, InputFormat};
+    use std::fs;
+
+    #[test]
+    fn test_encode_decode() {
+        let deposit = Transaction {
+            r#type: TransactionType::Deposit,
+            client: 513,
+            tx: 70000,
+            amount: Some(1.2345),
+        };
+        let record = encode(&deposit).unwrap();
+        assert_eq!(
+            record,
+            [0, 1, 2, 0x70, 0x11, 1, 0, 0x39, 0x30, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(decode(&record).unwrap(), deposit);
+        let dispute = Transaction {
+            r#type: TransactionType::Dispute,
+            client: 1,
+            tx: 1,
+            amount: None,
+        };
+        assert_eq!(decode(&encode(&dispute).unwrap()).unwrap(), dispute);
+        let mut invalid = record;
+        invalid[0] = 9;
+        assert!(decode(&invalid).is_err());
+        let infinite = Transaction {
+            amount: Some(f64::INFINITY),
+            ..deposit
+        };
+        assert!(encode(&infinite).is_err());
+    }
+
+    #[test]
+    fn test_convert_from_csv() {
+        let path = &temp_path("test_convert_from_csv.bin");
+        let mut reader = open("testfiles/biggertestfile.csv", InputFormat::Csv).unwrap();
+        let converted = convert(reader.as_mut(), path).unwrap();
+        let read = check_matches_csv(path, InputFormat::Binary);
+        assert_eq!(converted, read.len() as u64);
+        // records are numbered from one, as there are no lines
+        assert_eq!(read[1].0, 2);
+
+        // a truncated record is reported with its record number
+        let bytes = fs::read(path).unwrap();
+        fs::write(path, &bytes[..RECORD_SIZE + 4]).unwrap();
+        match read_all(path, InputFormat::Binary) {
+            Err(InputError::Record { record, .. }) => assert_eq!(record, 2),
+            other => panic!("Expected a truncated record, got {:?}", other),
+        }
+    }
+}
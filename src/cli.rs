@@ -0,0 +1,633 @@
+use toy_payments_engine::history::{parse_time, Period, PointInTime};
+use toy_payments_engine::input::{CsvDialect, InputFormat};
+use toy_payments_engine::output::{Column, OutputFormat};
+use toy_payments_engine::reconcile::Tolerance;
+use toy_payments_engine::statement::StatementFormat;
+
+/// How to run the program, printed when the arguments cannot be parsed
+pub const USAGE: &str = "Usage: cargo run -- [options] filename.csv > output.csv
+       cargo run -- [--serve address] [--http address] [--grpc address]
+       cargo run -- statement [statement options] events.csv > statement.csv
+       cargo run -- balance --client id (--after-tx tx | --at time) [--output-format format] events.csv
+
+Options:
+    --audit                 check account invariants after every transaction
+    --input-format format   the format of the input file: csv (the default), jsonl, parquet, iso20022 or binary
+    --delimiter char        the csv field delimiter, a comma by default. Use tab for tabs
+    --quote char            the csv quote character, a double quote by default
+    --comment char          skip csv lines that start with char, such as #
+    --mapping mapping.json  the names of the csv columns and transaction types, when they are not the usual ones
+    --account-map path      with iso20022 input, a csv of the client of each account id or IBAN
+    --convert path          instead of processing the input, write its transactions to path in the binary format.
+                            Can only be used with the input format options above
+    --output-format format  the format of the accounts: csv (the default), json, jsonl or parquet
+    --output path           write the accounts to path instead of stdout, compressed for .gz and .zst
+    --output-config path    a json file of the columns and number formatting of the accounts
+    --columns list          the columns of the accounts, separated by commas, out of client, available, held,
+                            total, locked, tx_count, open_disputes, last_tx, currency, negative and lock_reason
+    --precision places      always write amounts with this many decimal places
+    --thousands char        separate the thousands of amounts with char in csv output
+    --currency code         the currency of the currency column, such as EUR.
+                            The output options above can not be used with parquet output
+    --reconcile expected.csv
+                            compare the accounts with expected.csv by client, reporting missing and extra clients
+                            and differences on stderr, and exit with status 1 if they do not match
+    --tolerance amount      with --reconcile, amounts match if they are at most amount apart
+    --relative-tolerance fraction
+                            with --reconcile, amounts match if they are at most this fraction of the larger one apart
+    --journal journal.csv   write the outcome of every transaction to journal.csv
+    --events events.csv     write every applied event to events.csv
+    --replay                the input file is an event log to replay instead of transactions
+    --load-snapshot path    restore the accounts from a snapshot before processing the input
+    --save-snapshot path    write a snapshot of the accounts after processing the input
+    --wal path              record every applied transaction in a write-ahead log,
+                            and resume from it if a previous run was interrupted
+    --checkpoint count      with --wal, checkpoint the accounts every count applied transactions
+    --sqlite ledger.db      keep the accounts and transactions in a SQLite database
+                            that is kept between runs, instead of in memory.
+                            Can not be used with --wal or --load-snapshot
+    --shards count          process the transactions on count threads, split by client.
+                            Can not be used with --journal, --events, --replay, --wal or --sqlite
+    --serve address         instead of processing a file, accept transactions over tcp on address,
+                            such as 127.0.0.1:7878, until stopped. Can not be used with other options
+    --http address          instead of processing a file, serve the REST API on address until stopped.
+                            Can be used with --serve and --grpc, they then share the same accounts
+    --grpc address          instead of processing a file, serve the gRPC API on address until stopped.
+                            Can be used with --serve and --http, they then share the same accounts
+
+Statement options, for the statements of clients from an event log written with --events:
+    --client id             the client to write the statement of, can be given several times. Every client by default
+    --from-tx tx            start at the deposit or withdrawal tx, with a single --client
+    --to-tx tx              end with the deposit or withdrawal tx, with a single --client
+    --from time             start at time, such as 2026-10-01, 2026-10-01T09:30:00Z or seconds since the unix epoch
+    --to time               end before time
+    --format format         the format of the statements: csv (the default), text or html
+    --output path           write the statements to path instead of stdout, compressed for .gz and .zst
+    --sqlite ledger.db      read the history of a SQLite ledger instead of an event log
+
+Balance options, for the balances of a client at a point in an event log written with --events:
+    --client id             the client to find the balances of
+    --after-tx tx           the balances right after the deposit or withdrawal tx was applied
+    --at time               the balances after everything applied up to time, written the same as for statements
+    --output-format format  the format of the balances, the same as for the accounts
+    --sqlite ledger.db      read the history of a SQLite ledger instead of an event log";
+
+/// Command line options, all options come before the input file name
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    /// Check account invariants after every transaction
+    pub audit: bool,
+    /// The format of the input file
+    pub input_format: InputFormat,
+    /// How the csv input is written
+    pub csv_dialect: CsvDialect,
+    /// The column and transaction type names of the csv input
+    pub mapping: Option<String>,
+    /// The client of each ISO 20022 account, as a csv of account and client
+    pub account_map: Option<String>,
+    /// Where to write the input transactions in the binary format, instead of processing them
+    pub convert: Option<String>,
+    /// The format of the accounts written to stdout
+    pub output_format: OutputFormat,
+    /// Where to write the accounts instead of stdout
+    pub output: Option<String>,
+    /// The columns and number formatting of the accounts, as a json file
+    pub output_config: Option<String>,
+    /// The columns of the accounts, instead of those of the output config
+    pub columns: Option<Vec<Column>>,
+    /// How many decimal places amounts are written with, instead of that of the output config
+    pub precision: Option<usize>,
+    /// The thousands separator of amounts, instead of that of the output config
+    pub thousands: Option<char>,
+    /// The currency of the currency column, instead of that of the output config
+    pub currency: Option<String>,
+    /// The expected accounts to reconcile the computed ones with
+    pub reconcile: Option<String>,
+    /// How far apart reconciled amounts can be
+    pub tolerance: Tolerance,
+    /// Where to write the per-transaction outcome journal
+    pub journal: Option<String>,
+    /// Where to write the log of applied events
+    pub events: Option<String>,
+    /// The input file is an event log to replay rather than transactions
+    pub replay: bool,
+    /// Snapshot to restore before processing
+    pub load_snapshot: Option<String>,
+    /// Where to write a snapshot after processing
+    pub save_snapshot: Option<String>,
+    /// Write-ahead log for crash-safe processing
+    pub wal: Option<String>,
+    /// How many applied transactions between write-ahead log checkpoints, 0 for none
+    pub checkpoint: u64,
+    /// SQLite database to use as the storage instead of memory
+    pub sqlite: Option<String>,
+    /// How many threads to process the transactions on, None for the single threaded path
+    pub shards: Option<usize>,
+    /// Address to accept transactions on over tcp, instead of processing a file
+    pub serve: Option<String>,
+    /// Address to serve the REST API on, instead of processing a file
+    pub http: Option<String>,
+    /// Address to serve the gRPC API on, instead of processing a file
+    pub grpc: Option<String>,
+    /// The transactions csv to process, empty when serving
+    pub input: String,
+}
+
+impl Options {
+    /// Parse the options from the program arguments, not including the program name
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - the arguments to parse
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut input = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--audit" => options.audit = true,
+                "--input-format" => options.input_format = value(&arg, args.next())?.parse()?,
+                "--delimiter" => options.csv_dialect.delimiter = byte(&arg, args.next())?,
+                "--quote" => options.csv_dialect.quote = byte(&arg, args.next())?,
+                "--comment" => options.csv_dialect.comment = Some(byte(&arg, args.next())?),
+                "--mapping" => options.mapping = Some(value(&arg, args.next())?),
+                "--account-map" => options.account_map = Some(value(&arg, args.next())?),
+                "--convert" => options.convert = Some(value(&arg, args.next())?),
+                "--output-format" => options.output_format = value(&arg, args.next())?.parse()?,
+                "--output" => options.output = Some(value(&arg, args.next())?),
+                "--output-config" => options.output_config = Some(value(&arg, args.next())?),
+                "--columns" => {
+                    options.columns = Some(Column::parse_list(&value(&arg, args.next())?)?)
+                }
+                "--precision" => {
+                    let precision = value(&arg, args.next())?
+                        .parse()
+                        .ok()
+                        .filter(|precision| *precision <= 17)
+                        .ok_or_else(|| String::from("--precision needs a number up to 17"))?;
+                    options.precision = Some(precision)
+                }
+                "--thousands" => options.thousands = Some(byte(&arg, args.next())? as char),
+                "--currency" => options.currency = Some(value(&arg, args.next())?),
+                "--reconcile" => options.reconcile = Some(value(&arg, args.next())?),
+                "--tolerance" => options.tolerance.absolute = fraction(&arg, args.next())?,
+                "--relative-tolerance" => options.tolerance.relative = fraction(&arg, args.next())?,
+                "--journal" => options.journal = Some(value(&arg, args.next())?),
+                "--events" => options.events = Some(value(&arg, args.next())?),
+                "--replay" => options.replay = true,
+                "--load-snapshot" => options.load_snapshot = Some(value(&arg, args.next())?),
+                "--save-snapshot" => options.save_snapshot = Some(value(&arg, args.next())?),
+                "--sqlite" => options.sqlite = Some(value(&arg, args.next())?),
+                "--wal" => options.wal = Some(value(&arg, args.next())?),
+                "--checkpoint" => {
+                    options.checkpoint = value(&arg, args.next())?
+                        .parse()
+                        .map_err(|_| String::from("--checkpoint needs a whole number"))?
+                }
+                "--serve" => options.serve = Some(value(&arg, args.next())?),
+                "--http" => options.http = Some(value(&arg, args.next())?),
+                "--grpc" => options.grpc = Some(value(&arg, args.next())?),
+                "--shards" => {
+                    let shards = value(&arg, args.next())?
+                        .parse()
+                        .ok()
+                        .filter(|shards| *shards > 0)
+                        .ok_or_else(|| String::from("--shards needs a number above 0"))?;
+                    options.shards = Some(shards)
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ => {
+                    if input.is_some() {
+                        return Err(format!("Unexpected argument {}", arg));
+                    }
+                    input = Some(arg);
+                }
+            }
+        }
+        if options.serve.is_some() || options.http.is_some() || options.grpc.is_some() {
+            let servers = Options {
+                serve: options.serve.clone(),
+                http: options.http.clone(),
+                grpc: options.grpc.clone(),
+                ..Options::default()
+            };
+            if input.is_some() || options != servers {
+                return Err(String::from(
+                    "--serve, --http and --grpc can not be used with an input file or other options",
+                ));
+            }
+            return Ok(options);
+        }
+        options.input = input.ok_or_else(|| String::from("No input file provided"))?;
+        if options.replay && options.wal.is_some() {
+            return Err(String::from("--wal can not be used with --replay"));
+        }
+        // the ledger already holds everything it committed, restoring on top of it would apply it twice
+        if options.sqlite.is_some() && (options.wal.is_some() || options.load_snapshot.is_some()) {
+            return Err(String::from(
+                "--sqlite can not be used with --wal or --load-snapshot",
+            ));
+        }
+        if options.replay && options.input_format != InputFormat::Csv {
+            return Err(String::from("--replay reads a csv event log"));
+        }
+        let csv_options = options.csv_dialect != CsvDialect::default() || options.mapping.is_some();
+        if csv_options && options.input_format != InputFormat::Csv {
+            return Err(String::from(
+                "--delimiter, --quote, --comment and --mapping need csv input",
+            ));
+        }
+        if options.account_map.is_some() && options.input_format != InputFormat::Iso20022 {
+            return Err(String::from("--account-map needs --input-format iso20022"));
+        }
+        let output_options = options.output_config.is_some()
+            || options.columns.is_some()
+            || options.precision.is_some()
+            || options.thousands.is_some()
+            || options.currency.is_some();
+        if output_options && options.output_format == OutputFormat::Parquet {
+            return Err(String::from(
+                "--output-config, --columns, --precision, --thousands and --currency can not be used with parquet output",
+            ));
+        }
+        if options.tolerance != Tolerance::default() && options.reconcile.is_none() {
+            return Err(String::from(
+                "--tolerance and --relative-tolerance need --reconcile",
+            ));
+        }
+        if options.convert.is_some() {
+            let conversion = Options {
+                input_format: options.input_format,
+                csv_dialect: options.csv_dialect,
+                mapping: options.mapping.clone(),
+                account_map: options.account_map.clone(),
+                convert: options.convert.clone(),
+                input: options.input.clone(),
+                ..Options::default()
+            };
+            if options != conversion {
+                return Err(String::from(
+                    "--convert can only be used with the input format options",
+                ));
+            }
+        }
+        let single_threaded = options.journal.is_some()
+            || options.events.is_some()
+            || options.replay
+            || options.wal.is_some()
+            || options.sqlite.is_some();
+        if options.shards.is_some() && single_threaded {
+            return Err(String::from(
+                "--shards can not be used with --journal, --events, --replay, --wal or --sqlite",
+            ));
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the statement subcommand
+#[derive(Debug, Default, PartialEq)]
+pub struct StatementOptions {
+    /// The clients to write statements of, every client if empty
+    pub clients: Vec<u16>,
+    /// The part of the history the statements cover
+    pub period: Period,
+    /// The format of the statements
+    pub format: StatementFormat,
+    /// Where to write the statements instead of stdout
+    pub output: Option<String>,
+    /// SQLite ledger to read the history of, instead of an event log
+    pub sqlite: Option<String>,
+    /// The event log to read the history of, empty with a SQLite ledger
+    pub events: String,
+}
+
+impl StatementOptions {
+    /// Parse the options from the arguments after the subcommand
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - the arguments to parse
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = StatementOptions::default();
+        let mut events = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--client" => options.clients.push(number(&arg, args.next())?),
+                "--from-tx" => options.period.from_tx = Some(number(&arg, args.next())?),
+                "--to-tx" => options.period.to_tx = Some(number(&arg, args.next())?),
+                "--from" => {
+                    options.period.from_time = Some(parse_time(&value(&arg, args.next())?)?)
+                }
+                "--to" => options.period.to_time = Some(parse_time(&value(&arg, args.next())?)?),
+                "--format" => options.format = value(&arg, args.next())?.parse()?,
+                "--output" => options.output = Some(value(&arg, args.next())?),
+                "--sqlite" => options.sqlite = Some(value(&arg, args.next())?),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ => {
+                    if events.is_some() {
+                        return Err(format!("Unexpected argument {}", arg));
+                    }
+                    events = Some(arg);
+                }
+            }
+        }
+        match (events, options.sqlite.is_some()) {
+            (Some(events), false) => options.events = events,
+            (None, true) => {}
+            _ => {
+                return Err(String::from(
+                    "A statement needs either an event log or --sqlite",
+                ))
+            }
+        }
+        let by_tx = options.period.from_tx.is_some() || options.period.to_tx.is_some();
+        if by_tx && options.clients.len() != 1 {
+            return Err(String::from("--from-tx and --to-tx need a single --client"));
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the balance subcommand
+#[derive(Debug, PartialEq)]
+pub struct BalanceOptions {
+    /// The client to find the balances of
+    pub client: u16,
+    /// The point in the client's history
+    pub point: PointInTime,
+    /// The format of the balances
+    pub output_format: OutputFormat,
+    /// SQLite ledger to read the history of, instead of an event log
+    pub sqlite: Option<String>,
+    /// The event log to read the history of, empty with a SQLite ledger
+    pub events: String,
+}
+
+impl BalanceOptions {
+    /// Parse the options from the arguments after the subcommand
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - the arguments to parse
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let (mut client, mut points) = (None, Vec::new());
+        let (mut output_format, mut sqlite, mut events) = (OutputFormat::default(), None, None);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--client" => client = Some(number(&arg, args.next())?),
+                "--after-tx" => points.push(PointInTime::AfterTx(number(&arg, args.next())?)),
+                "--at" => points.push(PointInTime::At(parse_time(&value(&arg, args.next())?)?)),
+                "--output-format" => output_format = value(&arg, args.next())?.parse()?,
+                "--sqlite" => sqlite = Some(value(&arg, args.next())?),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ => {
+                    if events.is_some() {
+                        return Err(format!("Unexpected argument {}", arg));
+                    }
+                    events = Some(arg);
+                }
+            }
+        }
+        let client = client.ok_or_else(|| String::from("A balance needs a --client"))?;
+        let point = match points[..] {
+            [point] => point,
+            _ => return Err(String::from("A balance needs either --after-tx or --at")),
+        };
+        let events = match (events, sqlite.is_some()) {
+            (Some(events), false) => events,
+            (None, true) => String::new(),
+            _ => {
+                return Err(String::from(
+                    "A balance needs either an event log or --sqlite",
+                ))
+            }
+        };
+        Ok(BalanceOptions {
+            client,
+            point,
+            output_format,
+            sqlite,
+            events,
+        })
+    }
+}
+
+/// The whole number an option was given
+fn number<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    self::value(option, value)?
+        .parse()
+        .map_err(|_| format!("{} needs a whole number", option))
+}
+
+/// The number an option was given, which can not be negative
+fn fraction(option: &str, value: Option<String>) -> Result<f64, String> {
+    self::value(option, value)?
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite() && *number >= 0.0)
+        .ok_or_else(|| format!("{} needs a number that is not negative", option))
+}
+
+/// The single ascii character an option was given, or tab
+fn byte(option: &str, value: Option<String>) -> Result<u8, String> {
+    let value = self::value(option, value)?;
+    match value.as_bytes() {
+        b"tab" | b"\\t" => Ok(b'\t'),
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!("{} needs a single ascii character", option)),
+    }
+}
+
+/// Make sure an option that needs a value was given one
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", option))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{BalanceOptions, Options, StatementOptions};
+    use toy_payments_engine::history::PointInTime;
+    use toy_payments_engine::input::InputFormat;
+    use toy_payments_engine::output::{Column, OutputFormat};
+    use toy_payments_engine::statement::StatementFormat;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse(&["--audit", "--journal", "journal.csv", "input.csv"]).unwrap();
+        assert!(options.audit);
+        assert_eq!(options.journal.as_deref(), Some("journal.csv"));
+        assert_eq!(options.input, "input.csv");
+        let options = parse(&["--replay", "events.csv"]).unwrap();
+        assert!(options.replay);
+        assert_eq!(options.events, None);
+        assert_eq!(options.input, "events.csv");
+        let options = parse(&[
+            "--load-snapshot",
+            "a.json",
+            "--save-snapshot",
+            "b.json",
+            "in.csv",
+        ])
+        .unwrap();
+        assert_eq!(options.load_snapshot.as_deref(), Some("a.json"));
+        assert_eq!(options.save_snapshot.as_deref(), Some("b.json"));
+        let options = parse(&["--wal", "run.wal", "--checkpoint", "1000", "in.csv"]).unwrap();
+        assert_eq!(options.wal.as_deref(), Some("run.wal"));
+        assert_eq!(options.checkpoint, 1000);
+        let options = parse(&["--sqlite", "ledger.db", "in.csv"]).unwrap();
+        assert_eq!(options.sqlite.as_deref(), Some("ledger.db"));
+        let options = parse(&[
+            "--input-format",
+            "jsonl",
+            "--output-format",
+            "json",
+            "in.jsonl",
+        ])
+        .unwrap();
+        assert_eq!(options.input_format, InputFormat::Jsonl);
+        assert_eq!(options.output_format, OutputFormat::Json);
+        let options = parse(&[
+            "--input-format",
+            "iso20022",
+            "--account-map",
+            "accounts.csv",
+            "camt053.xml",
+        ])
+        .unwrap();
+        assert_eq!(options.account_map.as_deref(), Some("accounts.csv"));
+        let options = parse(&["--delimiter", "tab", "--comment", "#", "in.tsv"]).unwrap();
+        assert_eq!(options.csv_dialect.delimiter, b'\t');
+        assert_eq!(options.csv_dialect.comment, Some(b'#'));
+        let options = parse(&["--mapping", "partner.json", "partner.csv"]).unwrap();
+        assert_eq!(options.mapping.as_deref(), Some("partner.json"));
+        let options = parse(&["--output", "accounts.csv.gz", "in.csv.zst"]).unwrap();
+        assert_eq!(options.output.as_deref(), Some("accounts.csv.gz"));
+        let options = parse(&[
+            "--columns",
+            "client,total,tx_count",
+            "--precision",
+            "2",
+            "--thousands",
+            ",",
+            "in.csv",
+        ])
+        .unwrap();
+        assert_eq!(
+            options.columns,
+            Some(vec![Column::Client, Column::Total, Column::TxCount])
+        );
+        assert_eq!(options.precision, Some(2));
+        assert_eq!(options.thousands, Some(','));
+        let options = parse(&[
+            "--reconcile",
+            "expected.csv",
+            "--tolerance",
+            "0.01",
+            "in.csv",
+        ])
+        .unwrap();
+        assert_eq!(options.reconcile.as_deref(), Some("expected.csv"));
+        assert_eq!(options.tolerance.absolute, 0.01);
+        let options = parse(&["--convert", "in.bin", "in.csv"]).unwrap();
+        assert_eq!(options.convert.as_deref(), Some("in.bin"));
+        let options = parse(&["--shards", "4", "in.csv"]).unwrap();
+        assert_eq!(options.shards, Some(4));
+        let options = parse(&["--serve", "127.0.0.1:7878"]).unwrap();
+        assert_eq!(options.serve.as_deref(), Some("127.0.0.1:7878"));
+        assert_eq!(options.input, "");
+        let options = parse(&["--serve", "127.0.0.1:7878", "--http", "127.0.0.1:8080"]).unwrap();
+        assert_eq!(options.http.as_deref(), Some("127.0.0.1:8080"));
+        let options = parse(&["--grpc", "127.0.0.1:50051"]).unwrap();
+        assert_eq!(options.grpc.as_deref(), Some("127.0.0.1:50051"));
+    }
+
+    #[test]
+    fn test_parse_bad_options() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--journal"]).is_err());
+        assert!(parse(&["--bogus", "input.csv"]).is_err());
+        assert!(parse(&["one.csv", "two.csv"]).is_err());
+        assert!(parse(&["--checkpoint", "often", "in.csv"]).is_err());
+        assert!(parse(&["--replay", "--wal", "run.wal", "events.csv"]).is_err());
+        assert!(parse(&["--shards", "0", "in.csv"]).is_err());
+        assert!(parse(&["--input-format", "xml", "in.xml"]).is_err());
+        assert!(parse(&["--account-map", "accounts.csv", "in.csv"]).is_err());
+        assert!(parse(&["--convert", "in.bin", "--audit", "in.csv"]).is_err());
+        assert!(parse(&["--delimiter", ";;", "in.csv"]).is_err());
+        assert!(parse(&["--mapping", "m.json", "--input-format", "binary", "in.bin"]).is_err());
+        assert!(parse(&["--quote", "'", "--input-format", "jsonl", "in.jsonl"]).is_err());
+        assert!(parse(&["--replay", "--input-format", "jsonl", "events.jsonl"]).is_err());
+        assert!(parse(&["--serve", "127.0.0.1:7878", "in.csv"]).is_err());
+        assert!(parse(&["--audit", "--serve", "127.0.0.1:7878"]).is_err());
+        assert!(parse(&["--http", "127.0.0.1:8080", "in.csv"]).is_err());
+        assert!(parse(&["--grpc", "127.0.0.1:50051", "--shards", "2"]).is_err());
+        assert!(parse(&["--shards", "2", "--journal", "j.csv", "in.csv"]).is_err());
+        assert!(parse(&["--sqlite", "l.db", "--wal", "w.wal", "in.csv"]).is_err());
+        assert!(parse(&["--sqlite", "l.db", "--load-snapshot", "s.json", "in.csv"]).is_err());
+        assert!(parse(&["--columns", "client,balance", "in.csv"]).is_err());
+        assert!(parse(&["--precision", "-1", "in.csv"]).is_err());
+        assert!(parse(&["--tolerance", "0.01", "in.csv"]).is_err());
+        assert!(parse(&["--reconcile", "e.csv", "--tolerance", "-1", "in.csv"]).is_err());
+        assert!(parse(&["--precision", "2", "--output-format", "parquet", "in.csv"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_statement_options() {
+        let parse = |args: &[&str]| StatementOptions::parse(args.iter().map(|arg| arg.to_string()));
+        let options = parse(&[
+            "--client",
+            "7",
+            "--from-tx",
+            "10",
+            "--format",
+            "html",
+            "events.csv",
+        ])
+        .unwrap();
+        assert_eq!(options.clients, [7]);
+        assert_eq!(options.period.from_tx, Some(10));
+        assert_eq!(options.format, StatementFormat::Html);
+        assert_eq!(options.events, "events.csv");
+        let options = parse(&["--from", "1970-01-02", "--sqlite", "ledger.db"]).unwrap();
+        assert_eq!(options.period.from_time, Some(86400));
+        assert_eq!(options.sqlite.as_deref(), Some("ledger.db"));
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--sqlite", "ledger.db", "events.csv"]).is_err());
+        assert!(parse(&["--from-tx", "10", "events.csv"]).is_err());
+        assert!(parse(&["--client", "seven", "events.csv"]).is_err());
+        assert!(parse(&["--from", "last week", "events.csv"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_balance_options() {
+        let parse = |args: &[&str]| BalanceOptions::parse(args.iter().map(|arg| arg.to_string()));
+        let options = parse(&["--client", "7", "--after-tx", "4012", "events.csv"]).unwrap();
+        assert_eq!(options.client, 7);
+        assert_eq!(options.point, PointInTime::AfterTx(4012));
+        assert_eq!(options.events, "events.csv");
+        let options = parse(&["--client", "7", "--at", "60", "--sqlite", "ledger.db"]).unwrap();
+        assert_eq!(options.point, PointInTime::At(60));
+
+        assert!(parse(&["--after-tx", "4012", "events.csv"]).is_err());
+        assert!(parse(&["--client", "7", "events.csv"]).is_err());
+        assert!(parse(&[
+            "--client",
+            "7",
+            "--after-tx",
+            "1",
+            "--at",
+            "60",
+            "events.csv"
+        ])
+        .is_err());
+        assert!(parse(&["--client", "7", "--after-tx", "1"]).is_err());
+    }
+}
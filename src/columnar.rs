@@ -0,0 +1,365 @@
+use crate::account::{Account, Transaction, TransactionType};
+use crate::input::{InputError, TransactionReader};
+use crate::output::Balances;
+use crate::wal::InputPosition;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, UInt16Type, UInt32Type};
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, UInt16Array, UInt32Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use std::fs::File;
+use std::io::Write;
+use std::sync::Arc;
+
+/// How many rows are decoded at a time
+const BATCH_SIZE: usize = 8192;
+
+/// The columns of a record batch of transactions, each cast once to the type the engine uses.
+/// The type column may be any string or dictionary column and the other columns any numeric type,
+/// the amount column is optional and may contain nulls.
+pub struct TransactionColumns {
+    r#type: StringArray,
+    client: UInt16Array,
+    tx: UInt32Array,
+    amount: Option<Float64Array>,
+}
+
+impl TransactionColumns {
+    /// Find and cast the columns of a record batch
+    ///
+    /// # Arguments
+    ///
+    /// * `batch` - the record batch with type, client, tx and optionally amount columns
+    pub fn new(batch: &RecordBatch) -> Result<Self, ArrowError> {
+        let column = |name: &str, data_type: &DataType| -> Result<ArrayRef, ArrowError> {
+            let column = batch
+                .column_by_name(name)
+                .ok_or_else(|| ArrowError::SchemaError(format!("missing the {} column", name)))?;
+            arrow_cast::cast(column, data_type)
+        };
+        let amount = match batch.column_by_name("amount") {
+            Some(_) => Some(column("amount", &DataType::Float64)?),
+            None => None,
+        };
+        Ok(TransactionColumns {
+            r#type: column("type", &DataType::Utf8)?.as_string::<i32>().clone(),
+            client: column("client", &DataType::UInt16)?
+                .as_primitive::<UInt16Type>()
+                .clone(),
+            tx: column("tx", &DataType::UInt32)?
+                .as_primitive::<UInt32Type>()
+                .clone(),
+            amount: amount.map(|amount| amount.as_primitive::<Float64Type>().clone()),
+        })
+    }
+
+    /// The number of rows
+    pub fn len(&self) -> usize {
+        self.r#type.len()
+    }
+
+    /// Whether there are no rows
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Decode a single row, the error says which column is invalid
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - the index of the row within the batch
+    pub fn transaction(&self, row: usize) -> Result<Transaction, String> {
+        if self.r#type.is_null(row) {
+            return Err("the type is missing".to_string());
+        }
+        let r#type = match self.r#type.value(row).trim() {
+            "deposit" => TransactionType::Deposit,
+            "withdrawal" => TransactionType::Withdrawal,
+            "dispute" => TransactionType::Dispute,
+            "resolve" => TransactionType::Resolve,
+            "chargeback" => TransactionType::Chargeback,
+            other => return Err(format!("unknown transaction type {}", other)),
+        };
+        // values that do not fit were cast to null
+        if self.client.is_null(row) {
+            return Err("the client is missing or does not fit in a u16".to_string());
+        }
+        if self.tx.is_null(row) {
+            return Err("the tx is missing or does not fit in a u32".to_string());
+        }
+        let amount = self
+            .amount
+            .as_ref()
+            .filter(|amount| amount.is_valid(row))
+            .map(|amount| amount.value(row));
+        Ok(Transaction {
+            r#type,
+            client: self.client.value(row),
+            tx: self.tx.value(row),
+            amount,
+        })
+    }
+}
+
+/// Decode a whole record batch of transactions, such as one read from an Arrow stream
+///
+/// # Arguments
+///
+/// * `batch` - the record batch with type, client, tx and optionally amount columns
+/// * `first_record` - the record number of the first row, used to report invalid rows
+pub fn transactions_from_batch(
+    batch: &RecordBatch,
+    first_record: u64,
+) -> Result<Vec<Transaction>, InputError> {
+    let columns = TransactionColumns::new(batch)?;
+    (0..columns.len())
+        .map(|row| {
+            columns
+                .transaction(row)
+                .map_err(|message| InputError::Record {
+                    record: first_record + row as u64,
+                    message,
+                })
+        })
+        .collect()
+}
+
+/// Reads transactions from a Parquet file one record batch at a time.
+/// Positions count records, the line of a transaction is its record number starting from 1.
+pub struct ParquetReader {
+    path: String,
+    batches: ParquetRecordBatchReader,
+    columns: Option<TransactionColumns>,
+    row: usize,
+    position: InputPosition,
+}
+
+impl ParquetReader {
+    /// Open a Parquet file
+    pub fn open(path: &str) -> Result<Self, InputError> {
+        Ok(ParquetReader {
+            path: path.to_string(),
+            batches: Self::batches(path, 0)?,
+            columns: None,
+            row: 0,
+            position: InputPosition {
+                byte: 0,
+                line: 1,
+                record: 0,
+            },
+        })
+    }
+
+    fn batches(path: &str, offset: u64) -> Result<ParquetRecordBatchReader, InputError> {
+        Ok(ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?
+            .with_batch_size(BATCH_SIZE)
+            .with_offset(offset as usize)
+            .build()?)
+    }
+}
+
+impl TransactionReader for ParquetReader {
+    fn next_transaction(&mut self) -> Option<Result<(u64, Transaction), InputError>> {
+        loop {
+            if let Some(columns) = &self.columns {
+                if self.row < columns.len() {
+                    let transaction = columns.transaction(self.row);
+                    let record = self.position.line;
+                    self.row += 1;
+                    self.position.line += 1;
+                    self.position.record += 1;
+                    return Some(
+                        transaction
+                            .map(|transaction| (record, transaction))
+                            .map_err(|message| InputError::Record { record, message }),
+                    );
+                }
+            }
+            let batch = match self.batches.next()? {
+                Ok(batch) => batch,
+                Err(error) => return Some(Err(error.into())),
+            };
+            match TransactionColumns::new(&batch) {
+                Ok(columns) => self.columns = Some(columns),
+                Err(error) => return Some(Err(error.into())),
+            }
+            self.row = 0;
+        }
+    }
+
+    fn position(&self) -> InputPosition {
+        self.position
+    }
+
+    fn seek(&mut self, position: InputPosition) -> Result<(), InputError> {
+        self.batches = Self::batches(&self.path, position.record)?;
+        self.columns = None;
+        self.position = position;
+        Ok(())
+    }
+}
+
+/// The accounts as a record batch with the same columns as the accounts csv
+///
+/// # Arguments
+///
+/// * `accounts` - the accounts, in the order of the rows
+pub fn accounts_batch(accounts: &[Account]) -> RecordBatch {
+    let balances: Vec<Balances> = accounts.iter().map(Balances::from).collect();
+    let schema = Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", DataType::Float64, false),
+        Field::new("held", DataType::Float64, false),
+        Field::new("total", DataType::Float64, false),
+        Field::new("locked", DataType::Boolean, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt16Array::from_iter_values(
+            balances.iter().map(|b| b.client),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            balances.iter().map(|b| b.available),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            balances.iter().map(|b| b.held),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            balances.iter().map(|b| b.total),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            balances.iter().map(|b| Some(b.locked)),
+        )),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns).expect("The accounts columns match the schema")
+}
+
+/// Write the accounts as a Parquet file
+///
+/// # Arguments
+///
+/// * `accounts` - the accounts to write, in the order they are written
+/// * `writer` - where to write the file
+pub fn write_accounts_parquet(
+    accounts: &[Account],
+    writer: impl Write + Send,
+) -> Result<(), ParquetError> {
+    let batch = accounts_batch(accounts);
+    let mut writer = ArrowWriter::try_new(writer, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Account, Transaction};
+    use crate::columnar::{transactions_from_batch, write_accounts_parquet};
+    use crate::input::{check_matches_csv, read_all, temp_path, InputError, InputFormat};
+    use arrow_array::{Array, ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ArrowWriter;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    /// A batch of transactions with the wider column types a warehouse export would have
+    fn export(transactions: &[Transaction]) -> RecordBatch {
+        let types = transactions.iter().map(|t| Some(t.r#type.to_string()));
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            ("type", Arc::new(StringArray::from_iter(types))),
+            (
+                "client",
+                Arc::new(Int64Array::from_iter_values(
+                    transactions.iter().map(|t| t.client.into()),
+                )),
+            ),
+            (
+                "tx",
+                Arc::new(Int64Array::from_iter_values(
+                    transactions.iter().map(|t| t.tx.into()),
+                )),
+            ),
+            (
+                "amount",
+                Arc::new(Float64Array::from_iter(
+                    transactions.iter().map(|t| t.amount),
+                )),
+            ),
+        ];
+        RecordBatch::try_from_iter(columns).unwrap()
+    }
+
+    #[test]
+    fn test_parquet_matches_csv() {
+        let csv: Vec<Transaction> = read_all("testfiles/biggertestfile.csv", InputFormat::Csv)
+            .unwrap()
+            .into_iter()
+            .map(|(_, transaction)| transaction)
+            .collect();
+        let path = &temp_path("test_parquet_matches_csv.parquet");
+        let batch = export(&csv);
+        let mut writer = ArrowWriter::try_new(File::create(path).unwrap(), batch.schema(), None)
+            .expect("Failed to create the parquet writer");
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        // records are numbered from one, as there are no lines
+        assert_eq!(check_matches_csv(path, InputFormat::Parquet)[1].0, 2);
+        assert_eq!(transactions_from_batch(&batch, 1).unwrap(), csv);
+
+        let mut invalid = csv[..3].to_vec();
+        invalid[2].client = 1;
+        let mut batch = export(&invalid);
+        let types = StringArray::from(vec!["deposit", "deposit", "refund"]);
+        batch = RecordBatch::try_new(
+            batch.schema(),
+            vec![
+                Arc::new(types),
+                batch.column(1).clone(),
+                batch.column(2).clone(),
+                batch.column(3).clone(),
+            ],
+        )
+        .unwrap();
+        match transactions_from_batch(&batch, 1) {
+            Err(InputError::Record { record, message }) => {
+                assert_eq!(
+                    (record, message.as_str()),
+                    (3, "unknown transaction type refund")
+                )
+            }
+            other => panic!("Expected an invalid record, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_write_accounts_parquet() {
+        let accounts = [
+            Account::with_balances(1, 1.5, 0.25, false),
+            Account::with_balances(2, 0.0, 0.0, true),
+        ];
+        let path = &temp_path("test_write_accounts_parquet.parquet");
+        write_accounts_parquet(&accounts, File::create(path).unwrap()).unwrap();
+        let batches: Vec<RecordBatch> =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+                .unwrap()
+                .build()
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        let names: Vec<&str> = batch
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(names, ["client", "available", "held", "total", "locked"]);
+        let total = batch.column_by_name("total").unwrap();
+        let total = total.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(total.values(), &[1.75, 0.0]);
+    }
+}
@@ -0,0 +1,244 @@
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The magic bytes a gzip file starts with
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The magic bytes a zstd frame starts with
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How a file is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The compression of a file to be written, from its extension
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the file, such as accounts.csv.gz
+    pub fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// The compression of an existing file, from its extension or otherwise its first bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the file
+    pub fn detect(path: &Path) -> io::Result<Self> {
+        let compression = Self::from_extension(path);
+        if compression != Compression::None {
+            return Ok(compression);
+        }
+        let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+        File::open(path)?
+            .take(ZSTD_MAGIC.len() as u64)
+            .read_to_end(&mut magic)?;
+        Ok(if magic.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        })
+    }
+}
+
+enum Stream {
+    Plain(File),
+    Gzip(MultiGzDecoder<BufReader<File>>),
+    Zstd(zstd::Decoder<'static, BufReader<File>>),
+}
+
+/// An input file that is decompressed while it is read, if it is compressed.
+/// Offsets are those of the decompressed data. Compressed files can only be read in order,
+/// so seeking is done by reading forward, and from the start again for an earlier offset.
+pub struct Decompressed {
+    path: PathBuf,
+    stream: Stream,
+    /// How much of the decompressed data has been read
+    offset: u64,
+}
+
+impl Decompressed {
+    /// Open a file that may be compressed with gzip or zstd
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the file
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let stream = match Compression::detect(path)? {
+            Compression::None => Stream::Plain(file),
+            Compression::Gzip => Stream::Gzip(MultiGzDecoder::new(BufReader::new(file))),
+            Compression::Zstd => Stream::Zstd(zstd::Decoder::new(file)?),
+        };
+        Ok(Decompressed {
+            path: path.to_path_buf(),
+            stream,
+            offset: 0,
+        })
+    }
+
+    /// How the file is compressed
+    pub fn compression(&self) -> Compression {
+        match self.stream {
+            Stream::Plain(_) => Compression::None,
+            Stream::Gzip(_) => Compression::Gzip,
+            Stream::Zstd(_) => Compression::Zstd,
+        }
+    }
+}
+
+impl Read for Decompressed {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = match &mut self.stream {
+            Stream::Plain(file) => file.read(buffer)?,
+            Stream::Gzip(decoder) => decoder.read(buffer)?,
+            Stream::Zstd(decoder) => decoder.read(buffer)?,
+        };
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for Decompressed {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        if let Stream::Plain(file) = &mut self.stream {
+            self.offset = file.seek(position)?;
+            return Ok(self.offset);
+        }
+        let target = match position {
+            SeekFrom::Start(target) => target,
+            SeekFrom::Current(0) => return Ok(self.offset),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "compressed input can only seek from the start",
+                ))
+            }
+        };
+        if target < self.offset {
+            *self = Decompressed::open(&self.path)?;
+        }
+        let skip = target - self.offset;
+        io::copy(&mut self.by_ref().take(skip), &mut io::sink())?;
+        if self.offset != target {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "the input ends {} bytes before the seek",
+                    target - self.offset
+                ),
+            ));
+        }
+        Ok(self.offset)
+    }
+}
+
+/// An output file that is compressed while it is written, if its extension asks for it.
+/// `finish` must be called once everything is written to complete the compressed data.
+pub enum Compressed {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Compressed {
+    /// Create an output file, compressed with gzip for .gz and with zstd for .zst
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the file to create
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = BufWriter::new(File::create(path)?);
+        Ok(match Compression::from_extension(path) {
+            Compression::None => Compressed::Plain(file),
+            Compression::Gzip => {
+                Compressed::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Compression::Zstd => Compressed::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// Complete the compressed data and flush the file
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Compressed::Plain(file) => file,
+            Compressed::Gzip(encoder) => encoder.finish()?,
+            Compressed::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for Compressed {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressed::Plain(file) => file.write(buffer),
+            Compressed::Gzip(encoder) => encoder.write(buffer),
+            Compressed::Zstd(encoder) => encoder.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressed::Plain(file) => file.flush(),
+            Compressed::Gzip(encoder) => encoder.flush(),
+            Compressed::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::{Compressed, Compression, Decompressed};
+    use crate::input::temp_path;
+    use std::fs;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    #[test]
+    fn test_round_trip_and_seek() {
+        let data = fs::read("testfiles/biggertestfile.csv").unwrap();
+        for name in ["round_trip.csv.gz", "round_trip.csv.zst", "round_trip.csv"] {
+            let path = temp_path(name);
+            let mut writer = Compressed::create(&path).unwrap();
+            writer.write_all(&data).unwrap();
+            writer.finish().unwrap();
+
+            // without the extension the magic bytes give the compression away
+            let renamed = format!("{}.input", path);
+            fs::rename(&path, &renamed).unwrap();
+            let expected = Compression::from_extension(Path::new(&path));
+            assert_eq!(Compression::detect(Path::new(&renamed)).unwrap(), expected);
+
+            let mut reader = Decompressed::open(&renamed).unwrap();
+            assert_eq!(reader.compression(), expected);
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            assert_eq!(read, data);
+            reader.seek(SeekFrom::Start(10)).unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, data[10..]);
+            if expected != Compression::None {
+                assert!(reader.seek(SeekFrom::Start(data.len() as u64 + 1)).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::account::{Transaction, TransactionType};
+use crate::compression::Decompressed;
+use crate::wal::drop_partial_line;
+use csv::WriterBuilder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The kinds of state changes that can be applied to an account
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// A deposit increased the available balance
+    Deposited,
+    /// A withdrawal decreased the available balance
+    Withdrew,
+    /// A deposit was disputed and its amount moved from available to held
+    Held,
+    /// A dispute was resolved and the amount moved from held back to available
+    Released,
+    /// A held deposit was reversed and the account frozen
+    ChargedBack,
+}
+
+/// The same name as in the event log
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EventKind::Deposited => "deposited",
+            EventKind::Withdrew => "withdrew",
+            EventKind::Held => "held",
+            EventKind::Released => "released",
+            EventKind::ChargedBack => "chargedback",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A state change that was applied to a client's account.
+/// Replaying every event in order rebuilds the accounts without the original input.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub client: u16,
+    /// The transaction that was deposited or withdrawn, or the deposit that was disputed
+    pub tx: u32,
+    /// The amount that moved, for disputes this is the amount of the disputed deposit
+    pub amount: f64,
+}
+
+/// An event as it is kept in the history, along with when it was applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedEvent {
+    pub event: Event,
+    /// Seconds since the unix epoch
+    pub time: u64,
+}
+
+/// A row of the event log, the event's fields followed by when it was applied
+#[derive(Debug, Deserialize, Serialize)]
+struct LogRow {
+    kind: EventKind,
+    client: u16,
+    tx: u32,
+    amount: f64,
+    time: u64,
+}
+
+/// The current time in seconds since the unix epoch, as it is kept in the history
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+impl Event {
+    /// The input transaction that applied the event.
+    /// Disputes, resolves and chargebacks carry the id of their deposit and have no amount of their own.
+    pub fn transaction(&self) -> Transaction {
+        let (r#type, amount) = match self.kind {
+            EventKind::Deposited => (TransactionType::Deposit, Some(self.amount)),
+            EventKind::Withdrew => (TransactionType::Withdrawal, Some(self.amount)),
+            EventKind::Held => (TransactionType::Dispute, None),
+            EventKind::Released => (TransactionType::Resolve, None),
+            EventKind::ChargedBack => (TransactionType::Chargeback, None),
+        };
+        Transaction {
+            r#type,
+            client: self.client,
+            tx: self.tx,
+            amount,
+        }
+    }
+}
+
+/// Writes every applied event to a csv file
+pub struct EventLog {
+    writer: csv::Writer<File>,
+    rows: u64,
+}
+
+impl EventLog {
+    /// Create a new event log file, or append to an existing one
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - where to write the event log csv
+    /// * `append` - keep what is already in the file, used when resuming an interrupted run
+    pub fn open(path: &str, append: bool) -> Result<Self, csv::Error> {
+        let rows = if append {
+            drop_partial_line(path)?;
+            match csv::Reader::from_path(path) {
+                Ok(mut reader) => reader.records().count() as u64,
+                Err(_) => 0,
+            }
+        } else {
+            0
+        };
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        // only write the headers if the file is new
+        let has_headers = file.metadata()?.len() == 0;
+        Ok(EventLog {
+            writer: WriterBuilder::new()
+                .has_headers(has_headers)
+                .from_writer(file),
+            rows,
+        })
+    }
+
+    /// Append a single applied event to the log
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - the applied event
+    /// * `time` - when it was applied, in seconds since the unix epoch
+    pub fn record(&mut self, event: &Event, time: u64) -> Result<(), csv::Error> {
+        self.writer.serialize(LogRow {
+            kind: event.kind.clone(),
+            client: event.client,
+            tx: event.tx,
+            amount: event.amount,
+            time,
+        })?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// How many events the log holds, counting those already in the file when it was opened
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Make sure everything recorded so far has been written to the file
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Make sure everything recorded so far has been written to the file and synced to disk
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+}
+
+/// Read every event from an event log file, in the order they were applied
+///
+/// # Arguments
+///
+/// * `path` - the event log csv to read, which may be compressed
+pub fn read_events(path: &str) -> Result<Vec<Event>, csv::Error> {
+    let history = read_history(path)?;
+    Ok(history.into_iter().map(|timed| timed.event).collect())
+}
+
+/// Read every event from an event log file along with when it was applied, in the order they were applied
+///
+/// # Arguments
+///
+/// * `path` - the event log csv to read, which may be compressed
+pub fn read_history(path: &str) -> Result<Vec<TimedEvent>, csv::Error> {
+    csv::Reader::from_reader(Decompressed::open(path)?)
+        .deserialize()
+        .map(|row| {
+            let row: LogRow = row?;
+            Ok(TimedEvent {
+                event: Event {
+                    kind: row.kind,
+                    client: row.client,
+                    tx: row.tx,
+                    amount: row.amount,
+                },
+                time: row.time,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,281 @@
+use crate::account::{Transaction, TransactionError, TransactionType};
+use crate::event::{Event, EventKind};
+use crate::observer::{Notification, NotificationKind};
+use crate::shared::SharedAccountManager;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+/// The types and service generated from proto/payments.proto
+pub mod proto {
+    tonic::include_proto!("payments");
+}
+
+use proto::payments_engine_server::{PaymentsEngine, PaymentsEngineServer};
+
+impl TryFrom<proto::Transaction> for Transaction {
+    type Error = Status;
+
+    fn try_from(transaction: proto::Transaction) -> Result<Self, Status> {
+        let r#type = match proto::TransactionType::try_from(transaction.r#type) {
+            Ok(proto::TransactionType::Deposit) => TransactionType::Deposit,
+            Ok(proto::TransactionType::Withdrawal) => TransactionType::Withdrawal,
+            Ok(proto::TransactionType::Dispute) => TransactionType::Dispute,
+            Ok(proto::TransactionType::Resolve) => TransactionType::Resolve,
+            Ok(proto::TransactionType::Chargeback) => TransactionType::Chargeback,
+            Ok(proto::TransactionType::Unspecified) | Err(_) => {
+                return Err(Status::invalid_argument(
+                    "type must be a deposit, withdrawal, dispute, resolve or chargeback",
+                ))
+            }
+        };
+        let client = u16::try_from(transaction.client)
+            .map_err(|_| Status::invalid_argument("client must fit in a u16"))?;
+        Ok(Transaction {
+            r#type,
+            client,
+            tx: transaction.tx,
+            amount: transaction.amount,
+        })
+    }
+}
+
+impl From<Event> for proto::Event {
+    fn from(event: Event) -> Self {
+        let kind = match event.kind {
+            EventKind::Deposited => proto::EventKind::Deposited,
+            EventKind::Withdrew => proto::EventKind::Withdrew,
+            EventKind::Held => proto::EventKind::Held,
+            EventKind::Released => proto::EventKind::Released,
+            EventKind::ChargedBack => proto::EventKind::ChargedBack,
+        };
+        proto::Event {
+            kind: kind.into(),
+            client: event.client.into(),
+            tx: event.tx,
+            amount: event.amount,
+        }
+    }
+}
+
+impl From<TransactionError> for proto::Rejection {
+    fn from(error: TransactionError) -> Self {
+        match error {
+            TransactionError::InvalidAmount => proto::Rejection::InvalidAmount,
+            TransactionError::AccountFrozen => proto::Rejection::AccountFrozen,
+            TransactionError::InsufficientFunds => proto::Rejection::InsufficientFunds,
+            TransactionError::UnknownTransaction => proto::Rejection::UnknownTransaction,
+            TransactionError::NotADeposit => proto::Rejection::NotADeposit,
+            TransactionError::AlreadyDisputed => proto::Rejection::AlreadyDisputed,
+            TransactionError::NotDisputed => proto::Rejection::NotDisputed,
+        }
+    }
+}
+
+impl From<Result<Event, TransactionError>> for proto::Outcome {
+    fn from(result: Result<Event, TransactionError>) -> Self {
+        let result = match result {
+            Ok(event) => proto::outcome::Result::Applied(event.into()),
+            Err(error) => proto::outcome::Result::Rejected(proto::Rejection::from(error).into()),
+        };
+        proto::Outcome {
+            result: Some(result),
+        }
+    }
+}
+
+impl From<Notification> for proto::Balances {
+    fn from(notification: Notification) -> Self {
+        proto::Balances {
+            client: notification.client.into(),
+            available: notification.available,
+            held: notification.held,
+            total: notification.total,
+            locked: notification.locked,
+        }
+    }
+}
+
+/// The gRPC service, backed by accounts that may be shared with the other servers
+pub struct PaymentsService {
+    account_manager: Arc<SharedAccountManager>,
+}
+
+impl PaymentsService {
+    /// Create the service, ready to be added to a tonic server
+    ///
+    /// # Arguments
+    ///
+    /// * `account_manager` - the accounts to serve
+    pub fn new(account_manager: Arc<SharedAccountManager>) -> PaymentsEngineServer<Self> {
+        PaymentsEngineServer::new(PaymentsService { account_manager })
+    }
+}
+
+#[tonic::async_trait]
+impl PaymentsEngine for PaymentsService {
+    async fn submit(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<proto::Outcome>, Status> {
+        let transaction = Transaction::try_from(request.into_inner())?;
+        let result = self.account_manager.process_transaction(transaction);
+        Ok(Response::new(result.into()))
+    }
+
+    async fn submit_batch(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<proto::Outcomes>, Status> {
+        let mut stream = request.into_inner();
+        // the whole batch is checked before any of it is applied, so an invalid transaction rejects the batch as a whole
+        let mut transactions = Vec::new();
+        while let Some(transaction) = stream.next().await {
+            transactions.push(Transaction::try_from(transaction?)?);
+        }
+        let outcomes = transactions
+            .into_iter()
+            .map(|transaction| self.account_manager.process_transaction(transaction).into())
+            .collect();
+        Ok(Response::new(proto::Outcomes { outcomes }))
+    }
+
+    type WatchBalancesStream = Pin<Box<dyn Stream<Item = Result<proto::Balances, Status>> + Send>>;
+
+    async fn watch_balances(
+        &self,
+        request: Request<proto::WatchRequest>,
+    ) -> Result<Response<Self::WatchBalancesStream>, Status> {
+        let clients = request.into_inner().clients;
+        let updates =
+            BroadcastStream::new(self.account_manager.subscribe()).filter_map(move |update| {
+                match update {
+                    Ok(notification)
+                        if notification.kind == NotificationKind::BalanceChanged
+                            && (clients.is_empty()
+                                || clients.contains(&notification.client.into())) =>
+                    {
+                        Some(Ok(notification.into()))
+                    }
+                    // updates missed by falling behind are skipped, the next one has the latest balances
+                    _ => None,
+                }
+            });
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grpc::proto::payments_engine_client::PaymentsEngineClient;
+    use crate::grpc::proto::{self, outcome, Rejection, TransactionType};
+    use crate::grpc::PaymentsService;
+    use crate::shared::SharedAccountManager;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    fn transaction(
+        r#type: TransactionType,
+        client: u32,
+        tx: u32,
+        amount: Option<f64>,
+    ) -> proto::Transaction {
+        proto::Transaction {
+            r#type: r#type.into(),
+            client,
+            tx,
+            amount,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grpc_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let service = PaymentsService::new(Arc::new(SharedAccountManager::new(4)));
+        tokio::spawn(
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        let mut client = PaymentsEngineClient::connect(address).await.unwrap();
+        let mut watch = client
+            .watch_balances(proto::WatchRequest { clients: vec![1] })
+            .await
+            .unwrap()
+            .into_inner();
+
+        let outcome = client
+            .submit(transaction(TransactionType::Deposit, 1, 1, Some(10.5)))
+            .await
+            .unwrap()
+            .into_inner();
+        match outcome.result {
+            Some(outcome::Result::Applied(event)) => assert_eq!(event.amount, 10.5),
+            other => panic!("Unexpected outcome {:?}", other),
+        }
+        let status = client
+            .submit(transaction(TransactionType::Deposit, 70000, 2, Some(1.0)))
+            .await;
+        assert_eq!(status.unwrap_err().code(), tonic::Code::InvalidArgument);
+        // a type that was left out or is not known is not taken for a deposit
+        let status = client
+            .submit(transaction(TransactionType::Unspecified, 1, 2, Some(1.0)))
+            .await;
+        assert_eq!(status.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let mut unknown = transaction(TransactionType::Deposit, 1, 2, Some(1.0));
+        unknown.r#type = 42;
+        let status = client.submit(unknown).await;
+        assert_eq!(status.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        let batch = vec![
+            transaction(TransactionType::Withdrawal, 1, 2, Some(20.0)),
+            transaction(TransactionType::Deposit, 2, 3, Some(1.0)),
+            transaction(TransactionType::Dispute, 1, 1, None),
+        ];
+        let outcomes = client
+            .submit_batch(tokio_stream::iter(batch))
+            .await
+            .unwrap()
+            .into_inner()
+            .outcomes;
+        assert_eq!(
+            outcomes[0].result,
+            Some(outcome::Result::Rejected(
+                Rejection::InsufficientFunds.into()
+            ))
+        );
+        assert_eq!(outcomes.len(), 3);
+        // an invalid transaction in the middle of a batch rejects it before anything is applied
+        let batch = vec![
+            transaction(TransactionType::Deposit, 3, 4, Some(1.0)),
+            transaction(TransactionType::Unspecified, 3, 5, Some(1.0)),
+        ];
+        let status = client.submit_batch(tokio_stream::iter(batch)).await;
+        assert_eq!(status.unwrap_err().code(), tonic::Code::InvalidArgument);
+        let outcome = client
+            .submit(transaction(TransactionType::Withdrawal, 3, 6, Some(1.0)))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            outcome.result,
+            Some(outcome::Result::Rejected(
+                Rejection::InsufficientFunds.into()
+            ))
+        );
+
+        // only the watched client's applied changes are streamed
+        let first = watch.message().await.unwrap().expect("No update");
+        assert_eq!((first.client, first.available, first.held), (1, 10.5, 0.0));
+        let second = watch.message().await.unwrap().expect("No update");
+        assert_eq!(
+            (second.client, second.available, second.held),
+            (1, 0.0, 10.5)
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use crate::account::Account;
+use crate::event::{EventKind, TimedEvent};
+use crate::storage::MemoryStorage;
+
+/// Seconds in a day
+const DAY: u64 = 24 * 60 * 60;
+
+/// Which part of a client's history to look at, by transaction or by when it was applied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Period {
+    /// Start at the deposit or withdrawal with this id
+    pub from_tx: Option<u32>,
+    /// End with the deposit or withdrawal with this id
+    pub to_tx: Option<u32>,
+    /// Start with the events applied at this time, in seconds since the unix epoch
+    pub from_time: Option<u64>,
+    /// End before the events applied at this time, in seconds since the unix epoch
+    pub to_time: Option<u64>,
+}
+
+impl Period {
+    /// Whether an event was applied before the period starts, given whether its start transaction was already seen
+    ///
+    /// # Arguments
+    ///
+    /// * `timed` - the event
+    /// * `started` - whether the event applying `from_tx` was already seen, or there is no `from_tx`
+    pub fn before(&self, timed: &TimedEvent, started: bool) -> bool {
+        !started || self.from_time.is_some_and(|from| timed.time < from)
+    }
+
+    /// Whether an event was applied after the period ends, by time.
+    /// The end transaction is found with `applies`, as the period ends right after it.
+    pub fn after(&self, timed: &TimedEvent) -> bool {
+        self.to_time.is_some_and(|to| timed.time >= to)
+    }
+}
+
+/// A point in a client's history to look at the balances of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    /// Right after the deposit or withdrawal with this id was applied
+    AfterTx(u32),
+    /// After every event applied up to and including this time, in seconds since the unix epoch
+    At(u64),
+}
+
+/// The balances of a client's account at a point in its history, rebuilt by replaying the history up to it.
+/// Only a scratch account is built, so nothing else is affected.
+///
+/// # Arguments
+///
+/// * `history` - every applied event, in the order they were applied
+/// * `client` - the client whose balances to find
+/// * `point` - the point in the client's history
+pub fn balance_at(
+    history: &[TimedEvent],
+    client: u16,
+    point: PointInTime,
+) -> Result<Account, String> {
+    let mut account = None;
+    let mut storage = MemoryStorage::default();
+    for timed in history.iter().filter(|timed| timed.event.client == client) {
+        if let PointInTime::At(time) = point {
+            if timed.time > time {
+                break;
+            }
+        }
+        let current = account.get_or_insert_with(|| Account::new(client));
+        current.apply_event(&timed.event, &mut storage);
+        if let PointInTime::AfterTx(tx) = point {
+            if applies(timed, tx) {
+                return Ok(*current);
+            }
+        }
+    }
+    match (point, account) {
+        (PointInTime::AfterTx(tx), _) => Err(format!(
+            "Client {} has no deposit or withdrawal {}",
+            client, tx
+        )),
+        (PointInTime::At(time), None) => Err(format!(
+            "Client {} had no account at {}",
+            client,
+            format_time(time)
+        )),
+        (PointInTime::At(_), Some(account)) => Ok(account),
+    }
+}
+
+/// Whether an event is the one that applied a deposit or withdrawal.
+/// Disputes, resolves and chargebacks have the id of the deposit they refer to, so they do not count.
+///
+/// # Arguments
+///
+/// * `timed` - the event
+/// * `tx` - the id of the deposit or withdrawal
+pub fn applies(timed: &TimedEvent, tx: u32) -> bool {
+    let kind = &timed.event.kind;
+    timed.event.tx == tx && (*kind == EventKind::Deposited || *kind == EventKind::Withdrew)
+}
+
+/// Format a time kept in the history as a UTC date and time, such as 2026-10-18 09:30:00
+///
+/// # Arguments
+///
+/// * `time` - seconds since the unix epoch
+pub fn format_time(time: u64) -> String {
+    let (year, month, day) = civil_from_days((time / DAY) as i64);
+    let seconds = time % DAY;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Parse a time given on the command line, as seconds since the unix epoch,
+/// a date such as 2026-10-18 for midnight at its start, or a UTC date and time such as 2026-10-18T09:30:00Z
+///
+/// # Arguments
+///
+/// * `text` - the time
+pub fn parse_time(text: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "Invalid time {}, expected seconds since the unix epoch, a date such as 2026-10-18 \
+             or a date and time such as 2026-10-18T09:30:00Z",
+            text
+        )
+    };
+    if let Ok(seconds) = text.parse() {
+        return Ok(seconds);
+    }
+    let text = text.strip_suffix('Z').unwrap_or(text);
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let numbers = |part: &str, separator| -> Option<Vec<u32>> {
+        part.split(separator)
+            .map(|number| number.parse().ok())
+            .collect()
+    };
+    let (year, month, day) = match numbers(date, '-').as_deref() {
+        Some(&[year, month, day]) => (year as i64, month, day),
+        _ => return Err(invalid()),
+    };
+    let days = days_from_civil(year, month, day);
+    // a date that does not exist, such as February 30th, comes back as another one
+    if year < 1970 || civil_from_days(days) != (year, month, day) {
+        return Err(invalid());
+    }
+    let seconds = match time.map(|time| numbers(time, ':')) {
+        None => 0,
+        Some(Some(parts)) if parts.len() == 2 || parts.len() == 3 => {
+            let (hours, minutes) = (parts[0], parts[1]);
+            let seconds = parts.get(2).copied().unwrap_or(0);
+            if hours > 23 || minutes > 59 || seconds > 59 {
+                return Err(invalid());
+            }
+            (hours * 3600 + minutes * 60 + seconds) as u64
+        }
+        Some(_) => return Err(invalid()),
+    };
+    Ok(days as u64 * DAY + seconds)
+}
+
+/// The days since the unix epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of a number of days since the unix epoch, as year, month and day
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::{Event, EventKind, TimedEvent};
+    use crate::history::{balance_at, format_time, parse_time, PointInTime};
+
+    #[test]
+    fn test_times() {
+        assert_eq!(parse_time("0").unwrap(), 0);
+        assert_eq!(parse_time("1970-01-01").unwrap(), 0);
+        let time = parse_time("2026-10-18T09:30:05Z").unwrap();
+        assert_eq!(time, 1_792_315_805);
+        assert_eq!(format_time(time), "2026-10-18 09:30:05");
+        assert_eq!(parse_time("2026-10-18 09:30").unwrap(), time - 5);
+        assert_eq!(
+            format_time(parse_time("2024-02-29").unwrap()),
+            "2024-02-29 00:00:00"
+        );
+        assert!(parse_time("2026-02-29").is_err());
+        assert!(parse_time("2026-10-18T24:00").is_err());
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_balance_at() {
+        let timed = |kind, client, tx, amount, time| TimedEvent {
+            event: Event {
+                kind,
+                client,
+                tx,
+                amount,
+            },
+            time,
+        };
+        let history = [
+            timed(EventKind::Deposited, 7, 1, 10.0, 100),
+            timed(EventKind::Deposited, 8, 2, 3.0, 150),
+            timed(EventKind::Withdrew, 7, 3, 4.0, 200),
+            timed(EventKind::Held, 7, 1, 10.0, 300),
+            timed(EventKind::ChargedBack, 7, 1, 10.0, 300),
+        ];
+        let account = balance_at(&history, 7, PointInTime::AfterTx(3)).unwrap();
+        assert_eq!(account.get_available_amount(), 6.0);
+        let account = balance_at(&history, 7, PointInTime::At(299)).unwrap();
+        assert_eq!(account.get_available_amount(), 6.0);
+        // everything applied at the time is included
+        let account = balance_at(&history, 7, PointInTime::At(300)).unwrap();
+        assert_eq!(account.get_total_amount(), -4.0);
+        assert!(account.is_frozen());
+        // a dispute carries the id of its deposit, but the balances are those right after the deposit
+        let account = balance_at(&history, 7, PointInTime::AfterTx(1)).unwrap();
+        assert_eq!(account.get_available_amount(), 10.0);
+        assert!(balance_at(&history, 7, PointInTime::AfterTx(2)).is_err());
+        assert!(balance_at(&history, 7, PointInTime::At(99)).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::account::{Transaction, TransactionError};
+use crate::account_manager::ACCOUNTS_HEADER;
+use crate::event::Event;
+use crate::output::Balances;
+use crate::shared::SharedAccountManager;
+use crate::storage::TransactionState;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// The outcome of a submitted transaction
+#[derive(Debug, Serialize)]
+pub struct Outcome {
+    pub applied: bool,
+    /// The change that was applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<Event>,
+    /// Why the transaction was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl From<Result<Event, TransactionError>> for Outcome {
+    fn from(result: Result<Event, TransactionError>) -> Self {
+        match result {
+            Ok(event) => Outcome {
+                applied: true,
+                event: Some(event),
+                reason: None,
+            },
+            Err(error) => Outcome {
+                applied: false,
+                event: None,
+                reason: Some(error.to_string()),
+            },
+        }
+    }
+}
+
+/// A stored transaction and whether it is under dispute
+#[derive(Debug, Serialize)]
+pub struct TransactionStatus {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    pub state: TransactionState,
+    pub disputed: bool,
+}
+
+/// The routes of the REST API, all backed by the same accounts:
+///  * `POST /transactions` - process one json transaction and return its outcome
+///  * `POST /transactions/batch` - process a json array of transactions in order and return their outcomes
+///  * `GET /accounts` - the accounts csv, the same as the program outputs
+///  * `GET /accounts/{client}` - a client's balances and lock state
+///  * `GET /accounts/{client}/transactions/{tx}` - a stored transaction and its dispute status
+///
+/// # Arguments
+///
+/// * `account_manager` - the accounts to serve
+pub fn router(account_manager: Arc<SharedAccountManager>) -> Router {
+    Router::new()
+        .route("/transactions", post(submit))
+        .route("/transactions/batch", post(submit_batch))
+        .route("/accounts", get(accounts_csv))
+        .route("/accounts/{client}", get(account))
+        .route("/accounts/{client}/transactions/{tx}", get(transaction))
+        .with_state(account_manager)
+}
+
+async fn submit(
+    State(account_manager): State<Arc<SharedAccountManager>>,
+    Json(transaction): Json<Transaction>,
+) -> Json<Outcome> {
+    Json(account_manager.process_transaction(transaction).into())
+}
+
+async fn submit_batch(
+    State(account_manager): State<Arc<SharedAccountManager>>,
+    Json(transactions): Json<Vec<Transaction>>,
+) -> Json<Vec<Outcome>> {
+    let outcomes = transactions
+        .into_iter()
+        .map(|transaction| account_manager.process_transaction(transaction).into())
+        .collect();
+    Json(outcomes)
+}
+
+async fn accounts_csv(
+    State(account_manager): State<Arc<SharedAccountManager>>,
+) -> impl IntoResponse {
+    let mut csv = format!("{}\n", ACCOUNTS_HEADER);
+    for account in account_manager.accounts() {
+        csv.push_str(&account.row());
+        csv.push('\n');
+    }
+    ([(header::CONTENT_TYPE, "text/csv")], csv)
+}
+
+async fn account(
+    State(account_manager): State<Arc<SharedAccountManager>>,
+    Path(client): Path<u16>,
+) -> Result<Json<Balances>, StatusCode> {
+    let account = account_manager
+        .find_account(client)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(Balances::from(&account)))
+}
+
+async fn transaction(
+    State(account_manager): State<Arc<SharedAccountManager>>,
+    Path((client, tx)): Path<(u16, u32)>,
+) -> Result<Json<TransactionStatus>, StatusCode> {
+    let (transaction, state) = account_manager
+        .lookup_transaction(client, tx)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(TransactionStatus {
+        transaction,
+        state,
+        disputed: state == TransactionState::Held,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::router;
+    use crate::shared::SharedAccountManager;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::Router;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// Send a request and return the status and body
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, String) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_rest_api() {
+        let app = router(Arc::new(SharedAccountManager::new(4)));
+        let deposit = json!({"type": "deposit", "client": 1, "tx": 1, "amount": 10.5});
+        let (status, body) = call(&app, "POST", "/transactions", Some(deposit)).await;
+        assert_eq!(status, StatusCode::OK);
+        let outcome: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(outcome["applied"], true);
+        assert_eq!(outcome["event"]["kind"], "deposited");
+
+        let batch = json!([
+            {"type": "withdrawal", "client": 1, "tx": 2, "amount": 20.0},
+            {"type": "dispute", "client": 1, "tx": 1, "amount": null},
+            {"type": "deposit", "client": 2, "tx": 3, "amount": 1.0},
+        ]);
+        let (_, body) = call(&app, "POST", "/transactions/batch", Some(batch)).await;
+        let outcomes: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(outcomes[0]["applied"], false);
+        assert_eq!(outcomes[0]["reason"], "insufficient funds");
+        assert_eq!(outcomes[1]["event"]["kind"], "held");
+        assert_eq!(outcomes[2]["applied"], true);
+
+        let (_, body) = call(&app, "GET", "/accounts/1", None).await;
+        let balances: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            balances,
+            json!({"client": 1, "available": 0.0, "held": 10.5, "total": 10.5, "locked": false})
+        );
+        let (status, _) = call(&app, "GET", "/accounts/9", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (_, body) = call(&app, "GET", "/accounts/1/transactions/1", None).await;
+        let status: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(status["state"], "held");
+        assert_eq!(status["disputed"], true);
+        assert_eq!(status["amount"], 10.5);
+        let (status, _) = call(&app, "GET", "/accounts/1/transactions/2", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (_, body) = call(&app, "GET", "/accounts", None).await;
+        assert_eq!(
+            body,
+            "client, available, held, total, locked\n1, 0.0, 10.5, 10.5, false\n2, 1.0, 0.0, 1.0, false\n"
+        );
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/transactions",
+            Some(json!({"type": "bogus"})),
+        )
+        .await;
+        assert!(status.is_client_error());
+    }
+}
@@ -0,0 +1,610 @@
+use crate::account::Transaction;
+use crate::binary::BinaryReader;
+use crate::columnar::ParquetReader;
+use crate::compression::Decompressed;
+use crate::iso20022::Iso20022Reader;
+use crate::mapping::ColumnMapping;
+use crate::wal::InputPosition;
+use arrow_schema::ArrowError;
+use csv::{ReaderBuilder, StringRecord, Trim};
+use parquet::errors::ParquetError;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::str::FromStr;
+
+/// The formats transactions can be read from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// A csv file with a header row, the columns are type, client, tx, amount
+    #[default]
+    Csv,
+    /// One json transaction per line, such as `{"type": "deposit", "client": 1, "tx": 1, "amount": 1.5}`
+    Jsonl,
+    /// A Parquet file with type, client, tx and amount columns, decoded a record batch at a time
+    Parquet,
+    /// An ISO 20022 camt.053 bank statement or pain.001 payment initiation
+    Iso20022,
+    /// Fixed-size little-endian records, see the binary module
+    Binary,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            "parquet" => Ok(InputFormat::Parquet),
+            "iso20022" => Ok(InputFormat::Iso20022),
+            "binary" => Ok(InputFormat::Binary),
+            _ => Err(format!(
+                "Unknown input format {}, expected csv, jsonl, parquet, iso20022 or binary",
+                name
+            )),
+        }
+    }
+}
+
+/// How a csv input is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvDialect {
+    /// The byte between fields
+    pub delimiter: u8,
+    /// The byte fields can be quoted with, so they can contain the delimiter
+    pub quote: u8,
+    /// Lines starting with this byte are skipped
+    pub comment: Option<u8>,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        CsvDialect {
+            delimiter: b',',
+            quote: b'"',
+            comment: None,
+        }
+    }
+}
+
+/// The ways reading transactions can fail
+#[derive(Debug)]
+pub enum InputError {
+    Io(io::Error),
+    Csv(csv::Error),
+    /// A line of a jsonl file that is not a valid transaction
+    Json {
+        line: u64,
+        error: serde_json::Error,
+    },
+    Parquet(ParquetError),
+    Arrow(ArrowError),
+    /// A record of a columnar file that is not a valid transaction, counting from 1
+    Record {
+        record: u64,
+        message: String,
+    },
+    /// An ISO 20022 document that could not be read
+    Iso20022(String),
+    /// A line of a csv file that is not a valid transaction, and why
+    Malformed {
+        line: u64,
+        message: String,
+    },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::Io(error) => write!(f, "input io error: {}", error),
+            InputError::Csv(error) => write!(f, "{}", error),
+            InputError::Json { line, error } => write!(f, "json error on line {}: {}", line, error),
+            InputError::Parquet(error) => write!(f, "{}", error),
+            InputError::Arrow(error) => write!(f, "{}", error),
+            InputError::Record { record, message } => {
+                write!(f, "invalid transaction in record {}: {}", record, message)
+            }
+            InputError::Iso20022(message) => write!(f, "iso 20022 error: {}", message),
+            InputError::Malformed { line, message } => {
+                write!(f, "invalid record on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for InputError {
+    fn from(error: io::Error) -> Self {
+        InputError::Io(error)
+    }
+}
+
+impl From<csv::Error> for InputError {
+    fn from(error: csv::Error) -> Self {
+        InputError::Csv(error)
+    }
+}
+
+impl From<ParquetError> for InputError {
+    fn from(error: ParquetError) -> Self {
+        InputError::Parquet(error)
+    }
+}
+
+impl From<ArrowError> for InputError {
+    fn from(error: ArrowError) -> Self {
+        InputError::Arrow(error)
+    }
+}
+
+/// Reads transactions from an input file, one at a time, in order
+pub trait TransactionReader {
+    /// The next transaction along with the line it started on, or None at the end of the input
+    fn next_transaction(&mut self) -> Option<Result<(u64, Transaction), InputError>>;
+
+    /// Where the input continues after the last transaction read
+    fn position(&self) -> InputPosition;
+
+    /// Continue reading from a position previously returned by `position`
+    fn seek(&mut self, position: InputPosition) -> Result<(), InputError>;
+
+    /// Anything in the input that was skipped instead of being read as a transaction
+    fn warnings(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Open an input file for reading transactions
+///
+/// # Arguments
+///
+/// * `path` - the input file
+/// * `format` - the format of the input file
+pub fn open(path: &str, format: InputFormat) -> Result<Box<dyn TransactionReader>, InputError> {
+    Ok(match format {
+        InputFormat::Csv => Box::new(CsvReader::open(path)?),
+        InputFormat::Jsonl => Box::new(JsonlReader::open(path)?),
+        InputFormat::Parquet => Box::new(ParquetReader::open(path)?),
+        InputFormat::Iso20022 => Box::new(Iso20022Reader::open(path, &HashMap::new())?),
+        InputFormat::Binary => Box::new(BinaryReader::open(path)?),
+    })
+}
+
+/// Reads transactions from a csv file with a header row
+pub struct CsvReader {
+    reader: csv::Reader<Decompressed>,
+    /// The header row as it is in the file, used in diagnostics
+    names: StringRecord,
+    /// The header row with mapped columns renamed to the transaction fields
+    headers: StringRecord,
+    record: StringRecord,
+    /// Where the type column is in the header row
+    type_column: usize,
+    comment: Option<u8>,
+    /// Other spellings of the transaction types, and their usual names
+    types: HashMap<String, String>,
+}
+
+impl CsvReader {
+    /// Open a csv file, which may be compressed, and read its header row
+    pub fn open(path: &str) -> Result<Self, InputError> {
+        Self::with_dialect(path, CsvDialect::default(), &ColumnMapping::default())
+    }
+
+    /// Open a csv file written in a dialect, which may be compressed, and read its header row
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the csv file
+    /// * `dialect` - how the file is written
+    /// * `mapping` - the names of its columns and transaction types
+    pub fn with_dialect(
+        path: &str,
+        dialect: CsvDialect,
+        mapping: &ColumnMapping,
+    ) -> Result<Self, InputError> {
+        // a utf-8 byte order mark at the start is skipped by the csv reader,
+        // comments are skipped here instead so that line numbers still count them
+        let reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .has_headers(false)
+            .delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .from_reader(Decompressed::open(path)?);
+        let mut csv_reader = CsvReader {
+            reader,
+            names: StringRecord::new(),
+            headers: StringRecord::new(),
+            record: StringRecord::new(),
+            type_column: 0,
+            comment: dialect.comment,
+            types: mapping
+                .types
+                .iter()
+                .map(|(spelling, r#type)| (spelling.clone(), r#type.to_string()))
+                .collect(),
+        };
+        csv_reader.read_record()?;
+        csv_reader.names = csv_reader.record.clone();
+        csv_reader.headers = csv_reader
+            .names
+            .iter()
+            .map(|name| mapping.field(name))
+            .collect();
+        let headers = &csv_reader.headers;
+        let column = |field: &str, name: &str| {
+            headers
+                .iter()
+                .position(|header| header == field)
+                .ok_or_else(|| InputError::Malformed {
+                    line: csv_reader
+                        .names
+                        .position()
+                        .map_or(1, |position| position.line()),
+                    message: format!("the header row has no {} column", name),
+                })
+        };
+        let type_column = column("type", &mapping.columns.r#type)?;
+        column("client", &mapping.columns.client)?;
+        column("tx", &mapping.columns.tx)?;
+        csv_reader.type_column = type_column;
+        Ok(csv_reader)
+    }
+
+    /// Replace another spelling of the record's transaction type with its usual name
+    fn map_type(&mut self) {
+        let Some(r#type) = self
+            .record
+            .get(self.type_column)
+            .and_then(|spelling| self.types.get(spelling))
+        else {
+            return;
+        };
+        let mut record =
+            StringRecord::with_capacity(self.record.as_slice().len(), self.record.len());
+        for (column, field) in self.record.iter().enumerate() {
+            record.push_field(if column == self.type_column {
+                r#type
+            } else {
+                field
+            });
+        }
+        record.set_position(self.record.position().cloned());
+        self.record = record;
+    }
+
+    /// Read the next record that is not a comment
+    /// Returns false at the end of the input
+    fn read_record(&mut self) -> Result<bool, csv::Error> {
+        while self.reader.read_record(&mut self.record)? {
+            let first = self.record.get(0).and_then(|field| field.bytes().next());
+            if self.comment.is_none() || first != self.comment {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Check the record has every field its transaction needs.
+    /// Only the amount may be left out, along with its comma, and only by disputes, resolves and chargebacks.
+    fn check_fields(&self, line: u64) -> Result<(), InputError> {
+        let malformed = |message| Err(InputError::Malformed { line, message });
+        if self.record.len() > self.headers.len() {
+            return malformed(format!(
+                "expected at most {} fields, found {}",
+                self.headers.len(),
+                self.record.len()
+            ));
+        }
+        let missing = self.headers.iter().zip(self.names.iter());
+        for (missing, name) in missing.skip(self.record.len()) {
+            if missing != "amount" {
+                return malformed(format!("the {} field is missing", name));
+            }
+            if let Some(r#type @ ("deposit" | "withdrawal")) = self.record.get(self.type_column) {
+                return malformed(format!("a {} needs an amount", r#type));
+            }
+        }
+        Ok(())
+    }
+
+    /// Explain which field of the record could not be deserialized, when it is known, and why
+    fn diagnose(&self, line: u64, error: csv::Error) -> InputError {
+        let csv::ErrorKind::Deserialize { err, .. } = error.kind() else {
+            return error.into();
+        };
+        let message = match err.field() {
+            Some(field) => format!(
+                "invalid {} {:?}: {}",
+                self.names.get(field as usize).unwrap_or("field"),
+                self.record.get(field as usize).unwrap_or_default(),
+                err.kind()
+            ),
+            None => err.kind().to_string(),
+        };
+        InputError::Malformed { line, message }
+    }
+}
+
+impl TransactionReader for CsvReader {
+    fn next_transaction(&mut self) -> Option<Result<(u64, Transaction), InputError>> {
+        match self.read_record() {
+            Ok(true) => {
+                let line = self.record.position().map_or(0, |position| position.line());
+                self.map_type();
+                let transaction = self.check_fields(line).and_then(|_| {
+                    self.record
+                        .deserialize(Some(&self.headers))
+                        .map_err(|error| self.diagnose(line, error))
+                });
+                Some(transaction.map(|transaction| (line, transaction)))
+            }
+            Ok(false) => None,
+            Err(error) => Some(Err(error.into())),
+        }
+    }
+
+    fn position(&self) -> InputPosition {
+        self.reader.position().into()
+    }
+
+    fn seek(&mut self, position: InputPosition) -> Result<(), InputError> {
+        Ok(self.reader.seek(position.into())?)
+    }
+}
+
+/// Reads transactions from a file with one json transaction per line, blank lines are skipped
+pub struct JsonlReader {
+    reader: BufReader<Decompressed>,
+    position: InputPosition,
+    line: String,
+}
+
+impl JsonlReader {
+    /// Open a jsonl file, which may be compressed
+    pub fn open(path: &str) -> Result<Self, InputError> {
+        Ok(JsonlReader {
+            reader: BufReader::new(Decompressed::open(path)?),
+            position: InputPosition {
+                byte: 0,
+                line: 1,
+                record: 0,
+            },
+            line: String::new(),
+        })
+    }
+}
+
+impl TransactionReader for JsonlReader {
+    fn next_transaction(&mut self) -> Option<Result<(u64, Transaction), InputError>> {
+        loop {
+            self.line.clear();
+            let read = match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(read) => read,
+                Err(error) => return Some(Err(error.into())),
+            };
+            let line = self.position.line;
+            self.position.byte += read as u64;
+            self.position.line += 1;
+            if line == 1 && self.line.starts_with('\u{feff}') {
+                // a byte order mark is not part of the first transaction
+                self.line.drain(..'\u{feff}'.len_utf8());
+            }
+            if self.line.trim().is_empty() {
+                continue;
+            }
+            self.position.record += 1;
+            return Some(
+                serde_json::from_str(&self.line)
+                    .map(|transaction| (line, transaction))
+                    .map_err(|error| InputError::Json { line, error }),
+            );
+        }
+    }
+
+    fn position(&self) -> InputPosition {
+        self.position
+    }
+
+    fn seek(&mut self, position: InputPosition) -> Result<(), InputError> {
+        self.reader.seek(SeekFrom::Start(position.byte))?;
+        self.position = position;
+        Ok(())
+    }
+}
+
+/// Read every transaction of a file along with its line, stopping at the first error
+///
+/// # Arguments
+///
+/// * `path` - the input file
+/// * `format` - the format of the input file
+#[cfg(test)]
+pub(crate) fn read_all(
+    path: &str,
+    format: InputFormat,
+) -> Result<Vec<(u64, Transaction)>, InputError> {
+    let mut reader = open(path, format)?;
+    let mut transactions = Vec::new();
+    while let Some(transaction) = reader.next_transaction() {
+        transactions.push(transaction?);
+    }
+    Ok(transactions)
+}
+
+/// Check that an input file holds the same transactions as testfiles/biggertestfile.csv,
+/// and that reading it resumes at the second transaction after seeking to the position after the first.
+/// Returns every transaction read along with its line or record number, for checks of the format itself.
+///
+/// # Arguments
+///
+/// * `path` - the input file, written from testfiles/biggertestfile.csv
+/// * `format` - the format of the input file
+#[cfg(test)]
+pub(crate) fn check_matches_csv(path: &str, format: InputFormat) -> Vec<(u64, Transaction)> {
+    let transactions = |read: &[(u64, Transaction)]| -> Vec<Transaction> {
+        read.iter()
+            .map(|(_, transaction)| transaction.clone())
+            .collect()
+    };
+    let csv = read_all("testfiles/biggertestfile.csv", InputFormat::Csv).unwrap();
+    let read = read_all(path, format).unwrap();
+    assert_eq!(transactions(&read), transactions(&csv));
+    let mut reader = open(path, format).unwrap();
+    reader.next_transaction().unwrap().unwrap();
+    let mut resumed = open(path, format).unwrap();
+    resumed.seek(reader.position()).unwrap();
+    assert_eq!(resumed.next_transaction().unwrap().unwrap(), read[1]);
+    read
+}
+
+/// A path in the temp directory for a file written by a test,
+/// starting with the process id so test runs at the same time do not overwrite each other's files
+///
+/// # Arguments
+///
+/// * `name` - the file name, its extension is kept
+#[cfg(test)]
+pub(crate) fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("{}_{}", std::process::id(), name))
+        .to_str()
+        .expect("Invalid temp path")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::TransactionType;
+    use crate::compression::Compressed;
+    use crate::input::{
+        check_matches_csv, open, read_all, temp_path, CsvDialect, CsvReader, InputError,
+        InputFormat, TransactionReader,
+    };
+    use crate::mapping::ColumnMapping;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn test_jsonl_matches_csv() {
+        let csv = read_all("testfiles/biggertestfile.csv", InputFormat::Csv).unwrap();
+        let path = &temp_path("test_jsonl_matches_csv.jsonl");
+        let lines: Vec<String> = csv
+            .iter()
+            .map(|(_, transaction)| serde_json::to_string(transaction).unwrap())
+            .collect();
+        fs::write(path, lines.join("\n")).unwrap();
+        // json lines count from the first line, as there is no header
+        assert_eq!(check_matches_csv(path, InputFormat::Jsonl)[0].0, 1);
+    }
+
+    #[test]
+    fn test_jsonl_errors_and_seek() {
+        let path = &temp_path("test_jsonl_errors_and_seek.jsonl");
+        fs::write(
+            path,
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": 2.5}\n\n\
+             {\"type\": \"dispute\", \"client\": 1, \"tx\": 1}\n\
+             {\"type\": \"refund\", \"client\": 1, \"tx\": 2}\n",
+        )
+        .unwrap();
+        let mut reader = open(path, InputFormat::Jsonl).unwrap();
+        let (line, first) = reader.next_transaction().unwrap().unwrap();
+        assert_eq!((line, first.amount), (1, Some(2.5)));
+        let after_first = reader.position();
+        // the blank line is skipped, and a missing amount is None
+        let (line, second) = reader.next_transaction().unwrap().unwrap();
+        assert_eq!(
+            (line, second.r#type.clone(), second.amount),
+            (3, TransactionType::Dispute, None)
+        );
+        match reader.next_transaction() {
+            Some(Err(InputError::Json { line, .. })) => assert_eq!(line, 4),
+            other => panic!("Expected a json error, got {:?}", other.map(|r| r.is_ok())),
+        }
+        reader.seek(after_first).unwrap();
+        assert_eq!(reader.next_transaction().unwrap().unwrap(), (3, second));
+    }
+
+    #[test]
+    fn test_compressed_csv() {
+        let csv = read_all("testfiles/biggertestfile.csv", InputFormat::Csv).unwrap();
+        let path = &temp_path("test_compressed_csv.csv.zst");
+        let mut writer = Compressed::create(path).unwrap();
+        writer
+            .write_all(&fs::read("testfiles/biggertestfile.csv").unwrap())
+            .unwrap();
+        writer.finish().unwrap();
+        // the lines are those of the decompressed input
+        assert_eq!(check_matches_csv(path, InputFormat::Csv), csv);
+    }
+
+    #[test]
+    fn test_tolerant_csv() {
+        let path = &temp_path("test_tolerant_csv.csv");
+        fs::write(
+            path,
+            "\u{feff}type;client;tx;amount\n\
+             # exported from the ledger\n\
+             deposit;1;1;\"2,5\"\n\
+             dispute;1;1\n\
+             resolve; 1; 1;\n",
+        )
+        .unwrap();
+        let dialect = CsvDialect {
+            delimiter: b';',
+            comment: Some(b'#'),
+            ..CsvDialect::default()
+        };
+        let mut reader = CsvReader::with_dialect(path, dialect, &ColumnMapping::default()).unwrap();
+        let mut read = Vec::new();
+        while let Some(transaction) = reader.next_transaction() {
+            read.push(transaction);
+        }
+        // the quoted decimal comma is not a number, and says so
+        match &read[0] {
+            Err(InputError::Malformed { line, message }) => assert_eq!(
+                (*line, message.as_str()),
+                (3, "invalid amount \"2,5\": invalid float literal")
+            ),
+            other => panic!("Expected a malformed record, got {:?}", other),
+        }
+        // a dispute can leave out the amount along with its comma
+        let (line, dispute) = read[1].as_ref().unwrap();
+        assert_eq!(
+            (*line, &dispute.r#type, dispute.amount),
+            (4, &TransactionType::Dispute, None)
+        );
+        assert_eq!(read[2].as_ref().unwrap().1.r#type, TransactionType::Resolve);
+
+        let malformed = |csv: &str| -> String {
+            fs::write(path, csv).unwrap();
+            let mut reader = open(path, InputFormat::Csv).unwrap();
+            reader.next_transaction().unwrap().unwrap_err().to_string()
+        };
+        assert_eq!(
+            malformed("type,client,tx,amount\ndeposit,1,1\n"),
+            "invalid record on line 2: a deposit needs an amount"
+        );
+        assert_eq!(
+            malformed("type,client,tx,amount\ndispute,1\n"),
+            "invalid record on line 2: the tx field is missing"
+        );
+        assert_eq!(
+            malformed("type,client,tx,amount\ndeposit,1,1,1.0,2.0\n"),
+            "invalid record on line 2: expected at most 4 fields, found 5"
+        );
+        assert_eq!(
+            malformed("type,client,tx,amount\nrefund,1,1,1.0\n"),
+            "invalid record on line 2: unknown variant `refund`, \
+             expected one of `deposit`, `withdrawal`, `dispute`, `resolve`, `chargeback`"
+        );
+        fs::write(path, "kind,client,tx,amount\n").unwrap();
+        assert_eq!(
+            open(path, InputFormat::Csv).err().unwrap().to_string(),
+            "invalid record on line 1: the header row has no type column"
+        );
+    }
+}
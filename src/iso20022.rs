@@ -0,0 +1,437 @@
+use crate::account::{Transaction, TransactionType};
+use crate::compression::Decompressed;
+use crate::input::{InputError, TransactionReader};
+use crate::wal::InputPosition;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+/// An entry of a statement or payment initiation that could not be translated into transactions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unmapped {
+    /// The line the entry starts on
+    pub line: u64,
+    /// The entry's reference, if it has one
+    pub reference: Option<String>,
+    pub reason: String,
+}
+
+impl fmt::Display for Unmapped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.reference {
+            Some(reference) => write!(
+                f,
+                "entry {} on line {}: {}",
+                reference, self.line, self.reason
+            ),
+            None => write!(f, "entry on line {}: {}", self.line, self.reason),
+        }
+    }
+}
+
+/// How the entries of a document were translated
+#[derive(Debug, Default, PartialEq)]
+pub struct MappingReport {
+    /// The number of entries that became transactions
+    pub mapped: usize,
+    /// The entries that were skipped, in document order
+    pub unmapped: Vec<Unmapped>,
+}
+
+impl fmt::Display for MappingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "mapped {} of {} entries",
+            self.mapped,
+            self.mapped + self.unmapped.len()
+        )?;
+        for unmapped in &self.unmapped {
+            write!(f, "\nskipped {}", unmapped)?;
+        }
+        Ok(())
+    }
+}
+
+/// The transactions translated from a document, each with the line of the entry it came from
+#[derive(Debug, Default, PartialEq)]
+pub struct Import {
+    pub transactions: Vec<(u64, Transaction)>,
+    pub report: MappingReport,
+}
+
+/// Translate a camt.053 bank statement or a pain.001 payment initiation into transactions.
+/// The document kind is detected from its root message, and any version of either message works.
+///
+/// In a camt.053 statement only booked entries are translated, credits become deposits and debits withdrawals,
+/// using the entry reference as the transaction id. A debit that reverses a credit, marked by the reversal indicator
+/// or a returned (RRTN) bank transaction code, becomes a dispute and a chargeback of the original deposit,
+/// which is the TxId of the entry's transaction details. A reversal without one is reported as unmapped.
+///
+/// In a pain.001 initiation every credit transfer is a withdrawal from the debtor account,
+/// using the instruction id or otherwise the end to end id as the transaction id.
+///
+/// # Arguments
+///
+/// * `xml` - the document
+/// * `accounts` - the client of each account id or IBAN, accounts that are not listed need a numeric id
+pub fn import(xml: &str, accounts: &HashMap<String, u16>) -> Result<Import, InputError> {
+    let document = Document::parse(xml).map_err(|error| InputError::Iso20022(error.to_string()))?;
+    let message = document
+        .root_element()
+        .children()
+        .find(Node::is_element)
+        .ok_or_else(|| InputError::Iso20022("the document is empty".to_string()))?;
+    let mut import = Import::default();
+    match message.tag_name().name() {
+        "BkToCstmrStmt" => {
+            for statement in children(message, "Stmt") {
+                let client = client(child(statement, "Acct"), accounts);
+                for entry in children(statement, "Ntry") {
+                    let mapped = client
+                        .clone()
+                        .and_then(|client| statement_entry(entry, client));
+                    import.add(&document, entry, text(entry, &["NtryRef"]), mapped);
+                }
+            }
+        }
+        "CstmrCdtTrfInitn" => {
+            for payment in children(message, "PmtInf") {
+                let client = client(child(payment, "DbtrAcct"), accounts);
+                for transfer in children(payment, "CdtTrfTxInf") {
+                    let reference = text(transfer, &["PmtId", "InstrId"])
+                        .or_else(|| text(transfer, &["PmtId", "EndToEndId"]));
+                    let mapped = client
+                        .clone()
+                        .and_then(|client| credit_transfer(transfer, reference, client));
+                    import.add(&document, transfer, reference, mapped);
+                }
+            }
+        }
+        other => {
+            return Err(InputError::Iso20022(format!(
+                "{} is not a camt.053 statement or pain.001 payment initiation",
+                other
+            )))
+        }
+    }
+    Ok(import)
+}
+
+impl Import {
+    /// Record the outcome of translating one entry
+    fn add(
+        &mut self,
+        document: &Document,
+        entry: Node,
+        reference: Option<&str>,
+        mapped: Result<Vec<Transaction>, String>,
+    ) {
+        let line = document.text_pos_at(entry.range().start).row as u64;
+        match mapped {
+            Ok(transactions) => {
+                self.report.mapped += 1;
+                self.transactions.extend(
+                    transactions
+                        .into_iter()
+                        .map(|transaction| (line, transaction)),
+                );
+            }
+            Err(reason) => self.report.unmapped.push(Unmapped {
+                line,
+                reference: reference.map(str::to_string),
+                reason,
+            }),
+        }
+    }
+}
+
+/// The transactions of a camt.053 entry
+fn statement_entry(entry: Node, client: u16) -> Result<Vec<Transaction>, String> {
+    // older versions have the status code directly in Sts
+    let status = text(entry, &["Sts", "Cd"]).or_else(|| text(entry, &["Sts"]));
+    if status != Some("BOOK") {
+        return Err(format!(
+            "the entry is not booked, its status is {}",
+            status.unwrap_or("missing")
+        ));
+    }
+    let tx = transaction_id(text(entry, &["NtryRef"]))?;
+    let amount = amount(text(entry, &["Amt"]))?;
+    let reversal = text(entry, &["RvslInd"]) == Some("true")
+        || text(entry, &["BkTxCd", "Domn", "Fmly", "SubFmlyCd"]) == Some("RRTN");
+    let transaction = |r#type, tx, amount| Transaction {
+        r#type,
+        client,
+        tx,
+        amount,
+    };
+    match (text(entry, &["CdtDbtInd"]), reversal) {
+        (Some("CRDT"), false) => Ok(vec![transaction(
+            TransactionType::Deposit,
+            tx,
+            Some(amount),
+        )]),
+        (Some("DBIT"), false) => Ok(vec![transaction(
+            TransactionType::Withdrawal,
+            tx,
+            Some(amount),
+        )]),
+        (Some("DBIT"), true) => {
+            let original = match text(entry, &["NtryDtls", "TxDtls", "Refs", "TxId"]) {
+                Some(original) => transaction_id(Some(original))?,
+                None => {
+                    return Err(
+                        "the reversal does not reference the original transaction".to_string()
+                    )
+                }
+            };
+            Ok(vec![
+                transaction(TransactionType::Dispute, original, None),
+                transaction(TransactionType::Chargeback, original, None),
+            ])
+        }
+        (Some("CRDT"), true) => Err("withdrawals can not be reversed".to_string()),
+        (indicator, _) => Err(format!(
+            "unknown credit or debit indicator {}",
+            indicator.unwrap_or("missing")
+        )),
+    }
+}
+
+/// The withdrawal of a pain.001 credit transfer
+fn credit_transfer(
+    transfer: Node,
+    reference: Option<&str>,
+    client: u16,
+) -> Result<Vec<Transaction>, String> {
+    let amount =
+        text(transfer, &["Amt", "InstdAmt"]).or_else(|| text(transfer, &["Amt", "EqvtAmt", "Amt"]));
+    Ok(vec![Transaction {
+        r#type: TransactionType::Withdrawal,
+        client,
+        tx: transaction_id(reference)?,
+        amount: Some(self::amount(amount)?),
+    }])
+}
+
+/// The client of an account, from the account map or a numeric account id
+fn client(account: Option<Node>, accounts: &HashMap<String, u16>) -> Result<u16, String> {
+    let id = account
+        .and_then(|account| {
+            text(account, &["Id", "IBAN"]).or_else(|| text(account, &["Id", "Othr", "Id"]))
+        })
+        .ok_or_else(|| "the account has no id".to_string())?;
+    accounts
+        .get(id)
+        .copied()
+        .or_else(|| id.parse().ok())
+        .ok_or_else(|| format!("account {} is not mapped to a client", id))
+}
+
+fn transaction_id(reference: Option<&str>) -> Result<u32, String> {
+    let reference = reference.ok_or_else(|| "the entry has no reference".to_string())?;
+    reference
+        .parse()
+        .map_err(|_| format!("reference {} is not a transaction id", reference))
+}
+
+fn amount(amount: Option<&str>) -> Result<f64, String> {
+    let amount = amount.ok_or_else(|| "the entry has no amount".to_string())?;
+    amount
+        .parse()
+        .map_err(|_| format!("invalid amount {}", amount))
+}
+
+/// The child elements with a name, ignoring namespaces
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// The trimmed text of the element at a path of child names
+fn text<'a>(node: Node<'a, '_>, path: &[&'static str]) -> Option<&'a str> {
+    let mut node = node;
+    for name in path {
+        node = child(node, name)?;
+    }
+    node.text().map(str::trim)
+}
+
+/// Load the client of each account from a csv file with account and client columns
+///
+/// # Arguments
+///
+/// * `path` - the csv file
+pub fn load_accounts(path: &str) -> Result<HashMap<String, u16>, InputError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let mut accounts = HashMap::new();
+    for record in reader.deserialize() {
+        let (account, client): (String, u16) = record?;
+        accounts.insert(account, client);
+    }
+    Ok(accounts)
+}
+
+/// Reads the transactions of an ISO 20022 document, which is translated as a whole when opened.
+/// Positions count transactions, and the line of a transaction is the line of the entry it came from.
+pub struct Iso20022Reader {
+    import: Import,
+    next: usize,
+}
+
+impl Iso20022Reader {
+    /// Open and translate a camt.053 or pain.001 document, which may be compressed
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the document
+    /// * `accounts` - the client of each account id or IBAN
+    pub fn open(path: &str, accounts: &HashMap<String, u16>) -> Result<Self, InputError> {
+        Ok(Iso20022Reader {
+            import: import(&io::read_to_string(Decompressed::open(path)?)?, accounts)?,
+            next: 0,
+        })
+    }
+
+    /// How the entries were translated
+    pub fn report(&self) -> &MappingReport {
+        &self.import.report
+    }
+}
+
+impl TransactionReader for Iso20022Reader {
+    fn next_transaction(&mut self) -> Option<Result<(u64, Transaction), InputError>> {
+        let transaction = self.import.transactions.get(self.next)?.clone();
+        self.next += 1;
+        Some(Ok(transaction))
+    }
+
+    fn position(&self) -> InputPosition {
+        InputPosition {
+            byte: 0,
+            line: 0,
+            record: self.next as u64,
+        }
+    }
+
+    fn seek(&mut self, position: InputPosition) -> Result<(), InputError> {
+        self.next = position.record as usize;
+        Ok(())
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        self.import
+            .report
+            .unmapped
+            .iter()
+            .map(|unmapped| format!("skipped {}", unmapped))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionType};
+    use crate::iso20022::{import, Unmapped};
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn transaction(
+        r#type: TransactionType,
+        client: u16,
+        tx: u32,
+        amount: Option<f64>,
+    ) -> Transaction {
+        Transaction {
+            r#type,
+            client,
+            tx,
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_camt053() {
+        let xml = fs::read_to_string("testfiles/camt053.xml").unwrap();
+        let imported = import(&xml, &HashMap::new()).unwrap();
+        let transactions: Vec<Transaction> = imported
+            .transactions
+            .iter()
+            .map(|(_, transaction)| transaction.clone())
+            .collect();
+        assert_eq!(
+            transactions,
+            [
+                transaction(TransactionType::Deposit, 1, 1, Some(100.5)),
+                transaction(TransactionType::Withdrawal, 1, 2, Some(20.25)),
+                transaction(TransactionType::Deposit, 1, 4, Some(30.0)),
+                transaction(TransactionType::Dispute, 1, 4, None),
+                transaction(TransactionType::Chargeback, 1, 4, None),
+            ]
+        );
+        assert_eq!(imported.transactions[0].0, 17);
+        assert_eq!(imported.report.mapped, 4);
+        assert_eq!(
+            imported.report.unmapped[0],
+            Unmapped {
+                line: 29,
+                reference: Some("3".to_string()),
+                reason: "the entry is not booked, its status is PDNG".to_string(),
+            }
+        );
+        let reasons: Vec<&str> = imported
+            .report
+            .unmapped
+            .iter()
+            .map(|unmapped| unmapped.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons[1..],
+            [
+                "reference FX-77 is not a transaction id",
+                "the reversal does not reference the original transaction",
+                "account DE89370400440532013000 is not mapped to a client",
+            ]
+        );
+
+        // mapping the IBAN to a client translates its entry too
+        let accounts = HashMap::from([("DE89370400440532013000".to_string(), 7)]);
+        let imported = import(&xml, &accounts).unwrap();
+        assert_eq!(
+            imported.transactions.last().unwrap().1,
+            transaction(TransactionType::Deposit, 7, 6, Some(7.0))
+        );
+        assert_eq!(imported.report.unmapped.len(), 3);
+    }
+
+    #[test]
+    fn test_pain001() {
+        let xml = fs::read_to_string("testfiles/pain001.xml").unwrap();
+        let imported = import(&xml, &HashMap::new()).unwrap();
+        let transactions: Vec<Transaction> = imported
+            .transactions
+            .into_iter()
+            .map(|(_, transaction)| transaction)
+            .collect();
+        assert_eq!(
+            transactions,
+            [
+                transaction(TransactionType::Withdrawal, 2, 10, Some(12.34)),
+                transaction(TransactionType::Withdrawal, 2, 11, Some(1.0)),
+            ]
+        );
+        assert!(import("<Document><Other/></Document>", &HashMap::new()).is_err());
+        assert!(import("<Document>", &HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::account::{round, Account, Transaction, TransactionError, TransactionType};
+use crate::event::Event;
+use crate::wal::drop_partial_line;
+use csv::{ReaderBuilder, WriterBuilder};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io;
+
+/// A single row of the journal, describing what happened to one input transaction
+#[derive(Debug, Serialize)]
+struct JournalEntry {
+    line: u64,
+    r#type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Option<f64>,
+    outcome: &'static str,
+    reason: Option<String>,
+    available: Option<f64>,
+    held: Option<f64>,
+    total: Option<f64>,
+}
+
+/// Writes a csv trail of every input transaction and what the engine did with it
+pub struct Journal {
+    writer: csv::Writer<File>,
+    last_line: u64,
+}
+
+impl Journal {
+    /// Create a new journal file, or append to an existing one
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - where to write the journal csv
+    /// * `append` - keep what is already in the file, used when resuming an interrupted run
+    pub fn open(path: &str, append: bool) -> Result<Self, csv::Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        // only write the headers if the file is new
+        let has_headers = file.metadata()?.len() == 0;
+        let last_line = if append {
+            drop_partial_line(path)?;
+            last_line(path)?
+        } else {
+            0
+        };
+        Ok(Journal {
+            writer: WriterBuilder::new()
+                .has_headers(has_headers)
+                .from_writer(file),
+            last_line,
+        })
+    }
+
+    /// Record the outcome of a single transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the line number of the transaction in the input file
+    /// * `transaction` - the transaction as it was read from the input
+    /// * `result` - what processing the transaction returned
+    /// * `account` - the affected account after processing, if it exists
+    pub fn record(
+        &mut self,
+        line: u64,
+        transaction: &Transaction,
+        result: &Result<Event, TransactionError>,
+        account: Option<&Account>,
+    ) -> Result<(), csv::Error> {
+        let (outcome, reason) = match result {
+            Ok(_) => ("applied", None),
+            Err(error) => ("rejected", Some(error.to_string())),
+        };
+        self.writer.serialize(JournalEntry {
+            line,
+            r#type: transaction.r#type.clone(),
+            client: transaction.client,
+            tx: transaction.tx,
+            amount: transaction.amount,
+            outcome,
+            reason,
+            available: account.map(|account| round(account.get_available_amount())),
+            held: account.map(|account| round(account.get_held_amount())),
+            total: account.map(|account| round(account.get_total_amount())),
+        })
+    }
+
+    /// Make sure everything recorded so far has been written to the file
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Make sure everything recorded so far has been written to the file and synced to disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// The input line of the last transaction already in the journal when it was opened, or 0 if there is none.
+    /// When resuming, the transactions rejected after the last applied one are processed again,
+    /// and the ones up to this line were already recorded by the interrupted run.
+    pub fn last_line(&self) -> u64 {
+        self.last_line
+    }
+}
+
+/// The largest input line recorded in an existing journal file, or 0 if it is empty
+///
+/// # Arguments
+///
+/// * `path` - the journal csv
+fn last_line(path: &str) -> Result<u64, csv::Error> {
+    let mut reader = ReaderBuilder::new().flexible(true).from_path(path)?;
+    let mut last_line = 0;
+    for record in reader.records() {
+        // a row that does not parse is skipped rather than failing the resume
+        let line = record.ok().and_then(|record| record.get(0)?.parse().ok());
+        last_line = last_line.max(line.unwrap_or(0));
+    }
+    Ok(last_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionError, TransactionType};
+    use crate::input::temp_path;
+    use crate::journal::Journal;
+    use std::fs;
+
+    #[test]
+    fn test_resume_journal() {
+        let path = &temp_path("test_resume_journal.csv");
+        let transaction = Transaction {
+            r#type: TransactionType::Dispute,
+            client: 1,
+            tx: 1,
+            amount: None,
+        };
+        let mut journal = Journal::open(path, false).unwrap();
+        assert_eq!(journal.last_line(), 0);
+        journal
+            .record(
+                2,
+                &transaction,
+                &Err(TransactionError::UnknownTransaction),
+                None,
+            )
+            .unwrap();
+        journal
+            .record(
+                5,
+                &transaction,
+                &Err(TransactionError::UnknownTransaction),
+                None,
+            )
+            .unwrap();
+        journal.sync().unwrap();
+        assert_eq!(Journal::open(path, true).unwrap().last_line(), 5);
+        // starting over forgets the previous run
+        assert_eq!(Journal::open(path, false).unwrap().last_line(), 0);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,29 @@
+//! A toy payments engine that processes transactions for multiple client accounts.
+//! The command line program in main.rs is built on top of this library.
+
+pub mod account;
+pub mod account_manager;
+pub mod actor;
+pub mod audit;
+pub mod binary;
+pub mod columnar;
+pub mod compression;
+pub mod event;
+pub mod grpc;
+pub mod history;
+pub mod http;
+pub mod input;
+pub mod iso20022;
+pub mod journal;
+pub mod mapping;
+pub mod observer;
+pub mod output;
+pub mod reconcile;
+pub mod server;
+pub mod sharded;
+pub mod shared;
+pub mod snapshot;
+pub mod sqlite_storage;
+pub mod statement;
+pub mod storage;
+pub mod wal;
@@ -1,33 +1,805 @@
-use crate::account_manager::AccountManager;
-use csv::{ReaderBuilder, Trim};
+use crate::cli::{BalanceOptions, Options, StatementOptions, USAGE};
 use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use toy_payments_engine::account::Account;
+use toy_payments_engine::account_manager::AccountManager;
+use toy_payments_engine::binary;
+use toy_payments_engine::compression::Compressed;
+use toy_payments_engine::event::{read_events, read_history, unix_time, EventLog, TimedEvent};
+use toy_payments_engine::grpc::PaymentsService;
+use toy_payments_engine::history::balance_at;
+use toy_payments_engine::http::router;
+use toy_payments_engine::input::{self, CsvReader, InputError, InputFormat, TransactionReader};
+use toy_payments_engine::iso20022::{load_accounts, Iso20022Reader};
+use toy_payments_engine::journal::Journal;
+use toy_payments_engine::mapping::ColumnMapping;
+use toy_payments_engine::output::{write_account_rows, write_accounts, OutputConfig, OutputFormat};
+use toy_payments_engine::reconcile::{read_expected, reconcile, Mismatch};
+use toy_payments_engine::server::serve;
+use toy_payments_engine::sharded::ShardedAccountManager;
+use toy_payments_engine::shared::SharedAccountManager;
+use toy_payments_engine::snapshot::{Snapshot, SnapshotError};
+use toy_payments_engine::sqlite_storage::SqliteStorage;
+use toy_payments_engine::statement::{statements, write_statements};
+use toy_payments_engine::storage::Storage;
+use toy_payments_engine::wal::{checkpoint_path, InputPosition, WalEntry, WriteAheadLog};
 
-mod account;
-mod account_manager;
+mod cli;
 
 /// A toy payments engine that process transactions for multiple client accounts
 fn main() {
-    let mut args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: cargo run -- filename.csv > output.csv");
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // subcommands come before any options
+    let subcommand = match args.first().map(String::as_str) {
+        Some("statement") => Some(
+            StatementOptions::parse(args.split_off(1)).map(|options| write_statement(&options)),
+        ),
+        Some("balance") => {
+            Some(BalanceOptions::parse(args.split_off(1)).map(|options| write_balance(&options)))
+        }
+        _ => None,
+    };
+    if let Some(result) = subcommand {
+        if let Err(error) = result {
+            println!("{}", error);
+            println!("{}", USAGE);
+        }
         return;
     }
-    let mut account_manager = AccountManager::default();
-    // parse the csv
-    let mut csv_reader = ReaderBuilder::new()
-        .trim(Trim::All)
-        .from_path(args.pop().expect("No valid file path provided"))
-        .expect("CSV Reader faiuled to parse");
-    for result in csv_reader.deserialize() {
-        match result {
-            Ok(transaction) => {
-                account_manager.process_transaction(transaction);
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("{}", error);
+            println!("{}", USAGE);
+            return;
+        }
+    };
+    if options.serve.is_some() || options.http.is_some() || options.grpc.is_some() {
+        run_servers(&options);
+        return;
+    }
+    if let Some(path) = &options.convert {
+        convert_input(&options, path);
+        return;
+    }
+    let output = match output_config(&options) {
+        Ok(output) => output,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
+    if let Some(shards) = options.shards {
+        run_sharded(&options, &output, shards);
+        return;
+    }
+    match &options.sqlite {
+        Some(path) => match SqliteStorage::open(path) {
+            Ok(storage) => run(&options, &output, AccountManager::new(storage)),
+            Err(error) => println!("Failed to open the ledger database: {}", error),
+        },
+        None => run(&options, &output, AccountManager::default()),
+    }
+}
+
+/// The columns and number formatting of the accounts, from the output config with the options given on top
+fn output_config(options: &Options) -> Result<OutputConfig, String> {
+    let mut config = match &options.output_config {
+        Some(path) => OutputConfig::load(path)
+            .map_err(|error| format!("Failed to load the output config: {}", error))?,
+        None => OutputConfig::default(),
+    };
+    if let Some(columns) = &options.columns {
+        config.columns = columns.clone();
+    }
+    config.precision = options.precision.or(config.precision);
+    config.thousands = options.thousands.or(config.thousands);
+    if let Some(currency) = &options.currency {
+        config.currency = Some(currency.clone());
+    }
+    config.check()?;
+    Ok(config)
+}
+
+/// Write the statements of clients from the history kept in an event log or SQLite ledger
+fn write_statement(options: &StatementOptions) {
+    let history = match load_history(options.sqlite.as_deref(), &options.events, &options.clients) {
+        Ok(history) => history,
+        Err(error) => {
+            println!("Failed to read the history: {}", error);
+            return;
+        }
+    };
+    let statements = match statements(&history, &options.clients, &options.period) {
+        Ok(statements) => statements,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
+    match &options.output {
+        None => write_statements(&statements, options.format, io::stdout())
+            .expect("Failed to write the statements"),
+        Some(path) => {
+            let mut file = Compressed::create(path).expect("Failed to create the statements file");
+            write_statements(&statements, options.format, &mut file)
+                .and_then(|_| file.finish())
+                .expect("Failed to write the statements file");
+        }
+    }
+}
+
+/// Write the balances of a client at a point in the history kept in an event log or SQLite ledger
+fn write_balance(options: &BalanceOptions) {
+    let balance = load_history(
+        options.sqlite.as_deref(),
+        &options.events,
+        &[options.client],
+    )
+    .map_err(|error| format!("Failed to read the history: {}", error))
+    .and_then(|history| balance_at(&history, options.client, options.point));
+    match balance {
+        Ok(account) => write_accounts(&[account], options.output_format, io::stdout())
+            .expect("Failed to write the balances"),
+        Err(error) => println!("{}", error),
+    }
+}
+
+/// Read the history kept in a SQLite ledger if one is given, or otherwise in an event log.
+/// Only the ledger can leave out the events of other clients, an event log is read as a whole.
+///
+/// # Arguments
+///
+/// * `sqlite` - the SQLite ledger
+/// * `events` - the event log
+/// * `clients` - the clients whose history is needed, or every client if empty
+fn load_history(
+    sqlite: Option<&str>,
+    events: &str,
+    clients: &[u16],
+) -> Result<Vec<TimedEvent>, String> {
+    match sqlite {
+        Some(path) => SqliteStorage::open(path)
+            .and_then(|storage| storage.history(clients))
+            .map_err(|error| error.to_string()),
+        None => read_history(events).map_err(|error| error.to_string()),
+    }
+}
+
+/// How many locks the accounts of the server are split between
+const SERVER_SHARDS: usize = 64;
+
+/// Run the tcp server, REST API and gRPC API given in the options until the program is stopped.
+/// All of them share the same accounts.
+fn run_servers(options: &Options) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the async runtime");
+    let account_manager = Arc::new(SharedAccountManager::new(SERVER_SHARDS));
+    let result: io::Result<()> = runtime.block_on(async {
+        let mut servers = Vec::new();
+        if let Some(address) = &options.serve {
+            let listener = TcpListener::bind(address).await?;
+            eprintln!("Listening for transactions on {}", listener.local_addr()?);
+            servers.push(tokio::spawn(serve(listener, account_manager.clone())));
+        }
+        if let Some(address) = &options.http {
+            let listener = TcpListener::bind(address).await?;
+            eprintln!("Serving the REST API on {}", listener.local_addr()?);
+            let app = router(account_manager.clone());
+            servers.push(tokio::spawn(
+                async move { axum::serve(listener, app).await },
+            ));
+        }
+        if let Some(address) = &options.grpc {
+            let listener = TcpListener::bind(address).await?;
+            eprintln!("Serving the gRPC API on {}", listener.local_addr()?);
+            let service = PaymentsService::new(account_manager.clone());
+            servers.push(tokio::spawn(async move {
+                Server::builder()
+                    .add_service(service)
+                    .serve_with_incoming(TcpListenerStream::new(listener))
+                    .await
+                    .map_err(io::Error::other)
+            }));
+        }
+        // the servers only return if they fail
+        for server in servers {
+            server.await??;
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        println!("The server stopped: {}", error);
+    }
+}
+
+/// Process the input with the given account manager, and output the accounts
+fn run<S: Storage>(
+    options: &Options,
+    output: &OutputConfig,
+    mut account_manager: AccountManager<S>,
+) {
+    let recovery = match restore(options, &mut account_manager) {
+        Ok(recovery) => recovery,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
+    account_manager.set_audit(options.audit);
+    if options.replay {
+        let events = read_events(&options.input).expect("Failed to read the event log");
+        account_manager.apply_events(&events);
+    } else if !process_transactions(options, &mut account_manager, recovery) {
+        return;
+    }
+    finish(options, output, account_manager);
+}
+
+/// Process the input on several threads split by client, and output the accounts
+///
+/// # Arguments
+///
+/// * `options` - the command line options
+/// * `output` - the columns and number formatting of the accounts
+/// * `shards` - how many threads to process the transactions on
+fn run_sharded(options: &Options, output: &OutputConfig, shards: usize) {
+    let mut sharded = match &options.load_snapshot {
+        Some(path) => match Snapshot::load(path) {
+            Ok(snapshot) => ShardedAccountManager::from_snapshot(shards, options.audit, snapshot),
+            Err(error) => {
+                println!("Failed to load the snapshot: {}", error);
+                return;
             }
+        },
+        None => ShardedAccountManager::new(shards, options.audit),
+    };
+    // parsing stays on this thread, the shards only process
+    let mut reader = match open_input(options) {
+        Ok(reader) => reader,
+        Err(error) => {
+            println!("Failed to open the input: {}", error);
+            return;
+        }
+    };
+    while let Some(result) = reader.next_transaction() {
+        match result {
+            Ok((_, transaction)) => sharded.process_transaction(transaction),
             Err(error) => {
-                println!("Failed to deserialize a transaction: {:?}", error);
+                println!("Failed to deserialize a transaction: {}", error);
                 return;
             }
         }
     }
-    account_manager.output_accounts();
+    report_warnings(reader.as_ref());
+    finish(options, output, sharded.finish());
+}
+
+/// Write the input transactions to a binary file instead of processing them
+///
+/// # Arguments
+///
+/// * `options` - the command line options
+/// * `path` - the binary file to write
+fn convert_input(options: &Options, path: &str) {
+    let result = open_input(options).and_then(|mut reader| {
+        let written = binary::convert(reader.as_mut(), path)?;
+        report_warnings(reader.as_ref());
+        Ok(written)
+    });
+    match result {
+        Ok(written) => println!("Converted {} transactions", written),
+        Err(error) => println!("Failed to convert the input: {}", error),
+    }
+}
+
+/// Open the input file, with the csv dialect and mapping or the account map for ISO 20022 documents if one was given
+fn open_input(options: &Options) -> Result<Box<dyn TransactionReader>, InputError> {
+    match (&options.account_map, options.input_format) {
+        (Some(path), _) => {
+            let accounts = load_accounts(path)?;
+            Ok(Box::new(Iso20022Reader::open(&options.input, &accounts)?))
+        }
+        (None, InputFormat::Csv) => {
+            let mapping = match &options.mapping {
+                Some(path) => ColumnMapping::load(path)?,
+                None => ColumnMapping::default(),
+            };
+            let reader = CsvReader::with_dialect(&options.input, options.csv_dialect, &mapping)?;
+            Ok(Box::new(reader))
+        }
+        (None, format) => input::open(&options.input, format),
+    }
+}
+
+/// Report anything the reader skipped on stderr, so it does not end up in the accounts csv
+fn report_warnings(reader: &dyn TransactionReader) {
+    for warning in reader.warnings() {
+        eprintln!("{}", warning);
+    }
+}
+
+/// Save the snapshot if requested, output the accounts and report any audit violations
+fn finish<S: Storage>(
+    options: &Options,
+    output: &OutputConfig,
+    account_manager: AccountManager<S>,
+) {
+    if let Some(path) = &options.save_snapshot {
+        if let Err(error) = account_manager.snapshot().save(path) {
+            println!("Failed to save the snapshot: {}", error);
+            return;
+        }
+    }
+    if let Some(path) = &options.wal {
+        // the run is complete, the next one starts from the saved snapshot
+        let _ = fs::remove_file(checkpoint_path(path));
+    }
+    let configured = *output != OutputConfig::default();
+    match (&options.output, options.output_format) {
+        (None, OutputFormat::Csv) if !configured => account_manager.output_accounts(),
+        (None, format) => write_output(&account_manager, format, output, io::stdout())
+            .expect("Failed to write the accounts"),
+        (Some(path), format) => {
+            let mut file = Compressed::create(path).expect("Failed to create the accounts file");
+            write_output(&account_manager, format, output, &mut file)
+                .and_then(|_| file.finish())
+                .expect("Failed to write the accounts file");
+        }
+    }
+    let reconciled = match &options.reconcile {
+        Some(path) => reconcile_accounts(options, path, &account_manager.accounts()),
+        None => true,
+    };
+    if options.audit {
+        // report violations on stderr so they do not end up in the accounts csv
+        let violations = account_manager.audit_report();
+        for violation in violations.iter() {
+            eprintln!("Audit violation: {}", violation);
+        }
+        if !violations.is_empty() {
+            process::exit(1);
+        }
+    }
+    if !reconciled {
+        process::exit(1);
+    }
+}
+
+/// Compare the computed accounts with the expected ones, reporting every mismatch and a summary on stderr
+/// Returns true if they match
+///
+/// # Arguments
+///
+/// * `options` - the command line options
+/// * `path` - the expected accounts csv
+/// * `accounts` - the computed accounts
+fn reconcile_accounts(options: &Options, path: &str, accounts: &[Account]) -> bool {
+    let expected = match read_expected(path) {
+        Ok(expected) => expected,
+        Err(error) => {
+            eprintln!("Failed to read the expected accounts: {}", error);
+            return false;
+        }
+    };
+    let mismatches = reconcile(accounts, &expected, &options.tolerance);
+    let (mut missing, mut extra, mut differences) = (0, 0, 0);
+    for mismatch in mismatches.iter() {
+        eprintln!("Reconciliation mismatch: {}", mismatch);
+        match mismatch {
+            Mismatch::Missing { .. } => missing += 1,
+            Mismatch::Extra { .. } => extra += 1,
+            Mismatch::Field { .. } => differences += 1,
+        }
+    }
+    eprintln!(
+        "Reconciled {} computed and {} expected accounts: {} missing, {} extra, {} differences",
+        accounts.len(),
+        expected.len(),
+        missing,
+        extra,
+        differences
+    );
+    mismatches.is_empty()
+}
+
+/// Write the accounts, only looking up what is on record for them if the output config asks for more than the balances
+///
+/// # Arguments
+///
+/// * `account_manager` - the accounts to write
+/// * `format` - the format to write them in
+/// * `output` - the columns and number formatting
+/// * `writer` - where to write them
+fn write_output<S: Storage>(
+    account_manager: &AccountManager<S>,
+    format: OutputFormat,
+    output: &OutputConfig,
+    writer: impl Write + Send,
+) -> io::Result<()> {
+    if *output == OutputConfig::default() {
+        write_accounts(&account_manager.accounts(), format, writer)
+    } else {
+        write_account_rows(&account_manager.accounts_activity(), format, output, writer)
+    }
+}
+
+/// The write-ahead log, and where to continue reading the input if a previous run was interrupted
+struct Recovery {
+    wal: WriteAheadLog,
+    resume_from: Option<InputPosition>,
+    /// The entries after the checkpoint, applied once the journal and event log are open
+    entries: Vec<WalEntry>,
+    /// How many events the event log held at the checkpoint
+    event_rows: u64,
+}
+
+/// Restore the accounts from the snapshot given in the options, and open the write-ahead log.
+/// A checkpoint left behind by an interrupted run takes the place of the snapshot,
+/// since it already includes everything the run started from.
+/// The log entries after the checkpoint are applied by `recover`.
+fn restore<S: Storage>(
+    options: &Options,
+    account_manager: &mut AccountManager<S>,
+) -> Result<Option<Recovery>, String> {
+    let checkpoint = options
+        .wal
+        .as_deref()
+        .map(checkpoint_path)
+        .filter(|path| Path::new(path).exists());
+    let (last_seq, event_rows, resume_from) =
+        match checkpoint.as_ref().or(options.load_snapshot.as_ref()) {
+            Some(path) => {
+                let snapshot = Snapshot::load(path)
+                    .map_err(|error| format!("Failed to load the snapshot: {}", error))?;
+                let (last_seq, event_rows, position) =
+                    (snapshot.wal_seq, snapshot.event_rows, snapshot.position);
+                account_manager.load_snapshot(snapshot);
+                (last_seq, event_rows, position)
+            }
+            None => (0, 0, None),
+        };
+    let recovery = match &options.wal {
+        Some(path) => {
+            let (wal, entries) = WriteAheadLog::open(path, last_seq)
+                .map_err(|error| format!("Failed to open the write-ahead log: {}", error))?;
+            let resume_from = entries.last().map(|entry| entry.position).or(resume_from);
+            Some(Recovery {
+                wal,
+                resume_from,
+                entries,
+                event_rows,
+            })
+        }
+        None => None,
+    };
+    Ok(recovery)
+}
+
+/// Apply the write-ahead log entries after the checkpoint, and write the ones the interrupted run
+/// did not get to write to the journal and event log.
+/// The event log rows after the checkpoint's belong to the entries in order,
+/// and the journal rows are found by their input line.
+///
+/// # Arguments
+///
+/// * `account_manager` - the accounts restored from the checkpoint
+/// * `recovery` - the entries after the checkpoint
+/// * `journal` - the journal, if one is being written
+/// * `event_log` - the event log, if one is being written
+fn recover<S: Storage>(
+    account_manager: &mut AccountManager<S>,
+    recovery: &mut Recovery,
+    mut journal: Option<&mut Journal>,
+    mut event_log: Option<&mut EventLog>,
+) {
+    for (index, entry) in recovery.entries.drain(..).enumerate() {
+        account_manager.apply_event(&entry.event);
+        if let Some(event_log) = event_log
+            .as_mut()
+            .filter(|event_log| event_log.rows() <= recovery.event_rows + index as u64)
+        {
+            event_log
+                .record(&entry.event, entry.time)
+                .expect("Failed to write to the event log");
+        }
+        if let Some(journal) = journal
+            .as_mut()
+            .filter(|journal| entry.line > journal.last_line())
+        {
+            let client = entry.event.client;
+            journal
+                .record(
+                    entry.line,
+                    &entry.event.transaction(),
+                    &Ok(entry.event),
+                    account_manager.find_account(&client).as_ref(),
+                )
+                .expect("Failed to write to the journal");
+        }
+    }
+}
+
+/// Save the accounts as the write-ahead log's checkpoint, then drop the log entries it covers.
+/// The journal and event log are synced first, as `recover` only fills them in from the entries after a checkpoint.
+///
+/// # Arguments
+///
+/// * `wal_path` - the path of the write-ahead log
+/// * `wal` - the write-ahead log
+/// * `account_manager` - the accounts to save
+/// * `position` - where the input continues after the last applied transaction
+/// * `journal` - the journal, if one is being written
+/// * `event_log` - the event log, if one is being written
+fn checkpoint<S: Storage>(
+    wal_path: &str,
+    wal: &mut WriteAheadLog,
+    account_manager: &AccountManager<S>,
+    position: InputPosition,
+    journal: Option<&mut Journal>,
+    event_log: Option<&mut EventLog>,
+) -> Result<(), SnapshotError> {
+    if let Some(journal) = journal {
+        journal.sync()?;
+    }
+    let mut event_rows = 0;
+    if let Some(event_log) = event_log {
+        event_log.sync()?;
+        event_rows = event_log.rows();
+    }
+    let mut snapshot = account_manager.snapshot();
+    snapshot.wal_seq = wal.last_seq();
+    snapshot.position = Some(position);
+    snapshot.event_rows = event_rows;
+    snapshot.save(&checkpoint_path(wal_path))?;
+    wal.truncate()?;
+    Ok(())
+}
+
+/// Process every transaction in the input file, writing the journal and event log if requested
+/// Returns false if the input could not be parsed
+fn process_transactions<S: Storage>(
+    options: &Options,
+    account_manager: &mut AccountManager<S>,
+    mut recovery: Option<Recovery>,
+) -> bool {
+    let resume_from = recovery.as_ref().and_then(|recovery| recovery.resume_from);
+    // when resuming, keep what the interrupted run already wrote
+    let mut journal = options.journal.as_ref().map(|path| {
+        Journal::open(path, resume_from.is_some()).expect("Failed to create the journal file")
+    });
+    let mut event_log = options.events.as_ref().map(|path| {
+        EventLog::open(path, resume_from.is_some()).expect("Failed to create the event log")
+    });
+    if let Some(recovery) = recovery.as_mut() {
+        recover(
+            account_manager,
+            recovery,
+            journal.as_mut(),
+            event_log.as_mut(),
+        );
+    }
+    let mut reader = match open_input(options) {
+        Ok(reader) => reader,
+        Err(error) => {
+            println!("Failed to open the input: {}", error);
+            return false;
+        }
+    };
+    if let Some(position) = resume_from {
+        reader
+            .seek(position)
+            .expect("Failed to resume reading the input");
+    }
+    let mut applied_since_checkpoint = 0;
+    while let Some(result) = reader.next_transaction() {
+        let (line, transaction) = match result {
+            Ok(read) => read,
+            Err(error) => {
+                println!("Failed to deserialize a transaction: {}", error);
+                return false;
+            }
+        };
+        let client = transaction.client;
+        // rejected transactions are otherwise ignored
+        let result = account_manager.process_transaction(transaction.clone());
+        let time = unix_time();
+        if let (Some(recovery), Ok(event)) = (recovery.as_mut(), &result) {
+            // the transaction must be durable before anything else sees it
+            recovery
+                .wal
+                .append(reader.position(), line, time, event)
+                .expect("Failed to write to the write-ahead log");
+            applied_since_checkpoint += 1;
+        }
+        if let (Some(event_log), Ok(event)) = (event_log.as_mut(), &result) {
+            event_log
+                .record(event, time)
+                .expect("Failed to write to the event log");
+        }
+        // a resumed run processes the transactions rejected after the last applied one again,
+        // but the interrupted run may already have recorded them
+        if let Some(journal) = journal
+            .as_mut()
+            .filter(|journal| line > journal.last_line())
+        {
+            journal
+                .record(
+                    line,
+                    &transaction,
+                    &result,
+                    account_manager.find_account(&client).as_ref(),
+                )
+                .expect("Failed to write to the journal");
+        }
+        if let Some(recovery) = recovery.as_mut() {
+            if options.checkpoint > 0 && applied_since_checkpoint >= options.checkpoint {
+                let wal_path = options.wal.as_deref().unwrap_or_default();
+                checkpoint(
+                    wal_path,
+                    &mut recovery.wal,
+                    account_manager,
+                    reader.position(),
+                    journal.as_mut(),
+                    event_log.as_mut(),
+                )
+                .expect("Failed to checkpoint the write-ahead log");
+                applied_since_checkpoint = 0;
+            }
+        }
+    }
+    report_warnings(reader.as_ref());
+    if let Some(journal) = journal.as_mut() {
+        journal.flush().expect("Failed to write to the journal");
+    }
+    if let Some(event_log) = event_log.as_mut() {
+        event_log.flush().expect("Failed to write to the event log");
+    }
+    if let Some(recovery) = recovery.as_mut() {
+        // checkpoint at the end of the input, so a crash from here on does not reprocess anything
+        let wal_path = options.wal.as_deref().unwrap_or_default();
+        let position = reader.position();
+        checkpoint(
+            wal_path,
+            &mut recovery.wal,
+            account_manager,
+            position,
+            journal.as_mut(),
+            event_log.as_mut(),
+        )
+        .expect("Failed to checkpoint the write-ahead log");
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::Options;
+    use crate::{open_input, process_transactions, restore};
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::process;
+    use toy_payments_engine::account_manager::AccountManager;
+    use toy_payments_engine::event::{read_events, Event, EventKind, EventLog};
+    use toy_payments_engine::wal::WriteAheadLog;
+
+    #[test]
+    fn test_resume_between_checkpoints() {
+        let path = |name: &str| {
+            let path = env::temp_dir().join(format!("{}_{}", process::id(), name));
+            path.to_str().expect("Invalid temp path").to_string()
+        };
+        let (input, prefix) = (path("resume.csv"), path("resume_prefix.csv"));
+        let (wal, events, journal) = (
+            path("resume.wal"),
+            path("resume_events.csv"),
+            path("resume_journal.csv"),
+        );
+        let rows = "type, client, tx, amount\n\
+                    deposit, 1, 1, 10.0\n\
+                    deposit, 2, 2, 5.0\n\
+                    withdrawal, 1, 3, 3.0\n\
+                    withdrawal, 2, 4, 50.0\n\
+                    dispute, 2, 2,\n\
+                    deposit, 1, 5, 1.0\n";
+        fs::write(&input, rows).unwrap();
+        fs::write(&prefix, &rows[..rows.find("withdrawal, 1").unwrap()]).unwrap();
+        let _ = fs::remove_file(&wal);
+        let options = |input: &str| {
+            let args = [
+                "--wal",
+                &wal,
+                "--checkpoint",
+                "2",
+                "--events",
+                &events,
+                "--journal",
+                &journal,
+                input,
+            ];
+            Options::parse(args.map(String::from)).unwrap()
+        };
+
+        // the run checkpoints after the first two deposits
+        let options_prefix = options(&prefix);
+        let mut account_manager = AccountManager::default();
+        let recovery = restore(&options_prefix, &mut account_manager).unwrap();
+        assert!(process_transactions(
+            &options_prefix,
+            &mut account_manager,
+            recovery
+        ));
+
+        // then applies the withdrawal and the dispute, but dies with only part of the event log written
+        let options = options(&input);
+        let mut reader = open_input(&options).unwrap();
+        let mut positions = Vec::new();
+        while let Some(result) = reader.next_transaction() {
+            positions.push((result.unwrap().0, reader.position()));
+        }
+        let (mut log, _) = WriteAheadLog::open(&wal, 2).unwrap();
+        let withdrawal = Event {
+            kind: EventKind::Withdrew,
+            client: 1,
+            tx: 3,
+            amount: 3.0,
+        };
+        let dispute = Event {
+            kind: EventKind::Held,
+            client: 2,
+            tx: 2,
+            amount: 5.0,
+        };
+        log.append(positions[2].1, positions[2].0, 100, &withdrawal)
+            .unwrap();
+        log.append(positions[4].1, positions[4].0, 100, &dispute)
+            .unwrap();
+        EventLog::open(&events, true)
+            .and_then(|mut event_log| {
+                event_log.record(&withdrawal, 100)?;
+                Ok(event_log.sync()?)
+            })
+            .unwrap();
+        let mut file = OpenOptions::new().append(true).open(&events).unwrap();
+        file.write_all(b"held,2,").unwrap();
+        drop(file);
+
+        // resuming fills in what the event log and journal are missing, once
+        let mut account_manager = AccountManager::default();
+        let recovery = restore(&options, &mut account_manager).unwrap();
+        assert!(process_transactions(
+            &options,
+            &mut account_manager,
+            recovery
+        ));
+        let logged = read_events(&events).unwrap();
+        assert_eq!(logged.len(), 5);
+        assert_eq!(logged[3], dispute);
+        let mut replayed = AccountManager::default();
+        replayed.apply_events(&logged);
+        assert_eq!(replayed.accounts(), account_manager.accounts());
+        let journal = fs::read_to_string(&journal).unwrap();
+        let lines: Vec<&str> = journal
+            .lines()
+            .skip(1)
+            .map(|row| &row[..row.find(',').unwrap()])
+            .collect();
+        assert_eq!(lines, ["2", "3", "4", "6", "7"]);
+        for path in [
+            input,
+            prefix,
+            wal.clone(),
+            events,
+            crate::checkpoint_path(&wal),
+        ] {
+            let _ = fs::remove_file(path);
+        }
+    }
 }
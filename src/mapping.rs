@@ -0,0 +1,136 @@
+use crate::account::TransactionType;
+use crate::input::InputError;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// The header name of each transaction field in the input
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Columns {
+    pub r#type: String,
+    pub client: String,
+    pub tx: String,
+    pub amount: String,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Columns {
+            r#type: "type".to_string(),
+            client: "client".to_string(),
+            tx: "tx".to_string(),
+            amount: "amount".to_string(),
+        }
+    }
+}
+
+/// How the columns and transaction types of a partner's csv map onto transactions, such as
+///
+/// {"columns": {"type": "txn_type", "client": "customer_id", "tx": "reference", "amount": "value"},
+///  "types": {"DEP": "deposit", "WD": "withdrawal", "CB": "chargeback"}}
+///
+/// Columns that are left out keep their usual names, and the usual type names are always accepted too.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub columns: Columns,
+    /// Other spellings of the transaction types
+    pub types: HashMap<String, TransactionType>,
+}
+
+impl ColumnMapping {
+    /// Load a mapping from a json file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the json file
+    pub fn load(path: &str) -> Result<Self, InputError> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|error| InputError::Json {
+            line: error.line() as u64,
+            error,
+        })
+    }
+
+    /// The usual name of a header, the header itself when it is not mapped,
+    /// or nothing for a usual name that was mapped to another header and should be ignored
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - a header of the input
+    pub fn field<'a>(&'a self, header: &'a str) -> &'a str {
+        let columns = &self.columns;
+        let fields = [
+            (&columns.r#type, "type"),
+            (&columns.client, "client"),
+            (&columns.tx, "tx"),
+            (&columns.amount, "amount"),
+        ];
+        if let Some((_, field)) = fields.iter().find(|(column, _)| *column == header) {
+            return field;
+        }
+        if fields.iter().any(|(_, field)| *field == header) {
+            return "";
+        }
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionType};
+    use crate::input::temp_path;
+    use crate::input::{CsvDialect, CsvReader, TransactionReader};
+    use crate::mapping::ColumnMapping;
+    use std::fs;
+
+    #[test]
+    fn test_partner_csv() {
+        let mapping: ColumnMapping = serde_json::from_str(
+            r#"{"columns": {"type": "txn_type", "client": "customer_id", "tx": "reference", "amount": "value"},
+                "types": {"DEP": "deposit", "WD": "withdrawal", "CB": "chargeback"}}"#,
+        )
+        .unwrap();
+        assert_eq!(mapping.field("customer_id"), "client");
+        assert_eq!(mapping.field("client"), "");
+        assert_eq!(mapping.field("branch"), "branch");
+
+        let path = &temp_path("test_partner_csv.csv");
+        fs::write(
+            path,
+            "reference,customer_id,branch,txn_type,value\n\
+             1,7,north,DEP,10.0\n\
+             2,7,north,WD,2.5\n\
+             1,7,north,dispute,\n\
+             1,7,north,CB\n",
+        )
+        .unwrap();
+        let mut reader = CsvReader::with_dialect(path, CsvDialect::default(), &mapping).unwrap();
+        let mut transactions = Vec::new();
+        while let Some(transaction) = reader.next_transaction() {
+            transactions.push(transaction.unwrap().1);
+        }
+        let transaction = |r#type, tx, amount| Transaction {
+            r#type,
+            client: 7,
+            tx,
+            amount,
+        };
+        assert_eq!(
+            transactions,
+            [
+                transaction(TransactionType::Deposit, 1, Some(10.0)),
+                transaction(TransactionType::Withdrawal, 2, Some(2.5)),
+                transaction(TransactionType::Dispute, 1, None),
+                transaction(TransactionType::Chargeback, 1, None),
+            ]
+        );
+
+        // without the mapping the partner headers are missing the usual columns
+        assert!(
+            CsvReader::with_dialect(path, CsvDialect::default(), &ColumnMapping::default())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::account::{round, Account};
+use crate::event::{Event, EventKind};
+use serde::Serialize;
+use std::fmt;
+
+/// What happened to an account
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationKind {
+    /// Any applied transaction, the balances are the new ones
+    BalanceChanged,
+    /// A deposit was disputed and its amount is now held
+    Disputed,
+    /// A dispute was resolved and its amount is available again
+    Resolved,
+    /// A disputed deposit was charged back
+    ChargedBack,
+    /// The account was frozen and will not accept any more transactions
+    Locked,
+}
+
+/// A change to an account, sent to every subscriber of an AccountManager as soon as it is applied
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub client: u16,
+    /// The transaction that was deposited or withdrawn, or the deposit that was disputed
+    pub tx: u32,
+    /// The amount that moved
+    pub amount: f64,
+    /// The balances after the change, rounded the same way as the accounts csv
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+/// The same name as in the json notifications
+impl fmt::Display for NotificationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            NotificationKind::BalanceChanged => "balancechanged",
+            NotificationKind::Disputed => "disputed",
+            NotificationKind::Resolved => "resolved",
+            NotificationKind::ChargedBack => "chargedback",
+            NotificationKind::Locked => "locked",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Something that wants to hear about every change, called in the order the changes were applied
+pub type Observer = Box<dyn FnMut(&Notification) + Send>;
+
+/// The notifications for an applied event, in the order subscribers receive them.
+/// Every event changes the balances, disputes, resolves and chargebacks also get a notification of their own,
+/// and a chargeback is followed by the account being locked.
+///
+/// # Arguments
+///
+/// * `event` - the event that was just applied
+/// * `account` - the account after the event was applied
+pub fn notifications(event: &Event, account: &Account) -> Vec<Notification> {
+    let mut kinds = vec![NotificationKind::BalanceChanged];
+    match event.kind {
+        EventKind::Held => kinds.push(NotificationKind::Disputed),
+        EventKind::Released => kinds.push(NotificationKind::Resolved),
+        EventKind::ChargedBack => kinds.push(NotificationKind::ChargedBack),
+        EventKind::Deposited | EventKind::Withdrew => {}
+    }
+    if account.is_frozen() && event.kind == EventKind::ChargedBack {
+        kinds.push(NotificationKind::Locked);
+    }
+    kinds
+        .into_iter()
+        .map(|kind| Notification {
+            kind,
+            client: event.client,
+            tx: event.tx,
+            amount: event.amount,
+            available: round(account.get_available_amount()),
+            held: round(account.get_held_amount()),
+            total: round(account.get_total_amount()),
+            locked: account.is_frozen(),
+        })
+        .collect()
+}
@@ -0,0 +1,539 @@
+use crate::account::{round, round_amount, Account, Activity};
+use crate::account_manager::ACCOUNTS_HEADER;
+use crate::columnar::write_accounts_parquet;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::fs;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// The formats the accounts can be written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The accounts csv, with a header row
+    #[default]
+    Csv,
+    /// A json array of accounts
+    Json,
+    /// One json account per line
+    Jsonl,
+    /// A Parquet file with the same columns as the accounts csv
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => Err(format!(
+                "Unknown output format {}, expected csv, json, jsonl or parquet",
+                name
+            )),
+        }
+    }
+}
+
+/// A client's balances, rounded the same way as the accounts csv
+#[derive(Debug, Serialize)]
+pub struct Balances {
+    pub client: u16,
+    pub available: f64,
+    pub held: f64,
+    pub total: f64,
+    pub locked: bool,
+}
+
+impl From<&Account> for Balances {
+    fn from(account: &Account) -> Self {
+        Balances {
+            client: account.get_id(),
+            available: round(account.get_available_amount()),
+            held: round(account.get_held_amount()),
+            total: round(account.get_total_amount()),
+            locked: account.is_frozen(),
+        }
+    }
+}
+
+/// Write the accounts with the same fields as the accounts csv
+///
+/// # Arguments
+///
+/// * `accounts` - the accounts to write, in the order they are written
+/// * `format` - the format to write them in
+/// * `writer` - where to write them
+pub fn write_accounts(
+    accounts: &[Account],
+    format: OutputFormat,
+    mut writer: impl Write + Send,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Csv => {
+            writeln!(writer, "{}", ACCOUNTS_HEADER)?;
+            for account in accounts {
+                writeln!(writer, "{}", account.row())?;
+            }
+        }
+        OutputFormat::Json => {
+            let balances: Vec<Balances> = accounts.iter().map(Balances::from).collect();
+            serde_json::to_writer(&mut writer, &balances)?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Jsonl => {
+            for account in accounts {
+                serde_json::to_writer(&mut writer, &Balances::from(account))?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Parquet => {
+            write_accounts_parquet(accounts, &mut writer).map_err(io::Error::other)?
+        }
+    }
+    writer.flush()
+}
+
+/// A column of the accounts output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+    /// How many deposits and withdrawals are on record
+    TxCount,
+    /// How many deposits are under dispute
+    OpenDisputes,
+    /// The highest id of the deposits and withdrawals on record, empty if there are none
+    LastTx,
+    /// The currency given in the output config
+    Currency,
+    /// Whether the available balance is below zero, such as after disputing a deposit that was withdrawn
+    Negative,
+    /// Why the account is locked, empty if it is not
+    LockReason,
+}
+
+/// Every column, in the order they are listed in the usage
+const COLUMNS: [Column; 11] = [
+    Column::Client,
+    Column::Available,
+    Column::Held,
+    Column::Total,
+    Column::Locked,
+    Column::TxCount,
+    Column::OpenDisputes,
+    Column::LastTx,
+    Column::Currency,
+    Column::Negative,
+    Column::LockReason,
+];
+
+impl Column {
+    /// The name of the column in the header and in json
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Client => "client",
+            Column::Available => "available",
+            Column::Held => "held",
+            Column::Total => "total",
+            Column::Locked => "locked",
+            Column::TxCount => "tx_count",
+            Column::OpenDisputes => "open_disputes",
+            Column::LastTx => "last_tx",
+            Column::Currency => "currency",
+            Column::Negative => "negative",
+            Column::LockReason => "lock_reason",
+        }
+    }
+
+    /// Parse a comma separated list of column names, such as client,total,tx_count
+    ///
+    /// # Arguments
+    ///
+    /// * `list` - the column names
+    pub fn parse_list(list: &str) -> Result<Vec<Column>, String> {
+        list.split(',').map(|name| name.trim().parse()).collect()
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        COLUMNS
+            .into_iter()
+            .find(|column| column.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = COLUMNS.iter().map(Column::name).collect();
+                format!("Unknown column {}, expected {}", name, names.join(", "))
+            })
+    }
+}
+
+/// Which columns the accounts are written with and how their amounts look, such as
+///
+/// {"columns": ["client", "total", "currency", "tx_count"], "precision": 2, "thousands": ",", "currency": "EUR"}
+///
+/// Anything left out is the same as the accounts csv.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub columns: Vec<Column>,
+    /// How many decimal places amounts always have, instead of only as many as they need
+    pub precision: Option<usize>,
+    /// Separate the thousands of amounts with this character in csv output
+    pub thousands: Option<char>,
+    /// The currency of the currency column
+    pub currency: Option<String>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            columns: COLUMNS[..5].to_vec(),
+            precision: None,
+            thousands: None,
+            currency: None,
+        }
+    }
+}
+
+impl OutputConfig {
+    /// Load an output config from a json file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the json file
+    pub fn load(path: &str) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Check the config can be written, returning what is wrong with it
+    pub fn check(&self) -> Result<(), String> {
+        if self.columns.is_empty() {
+            return Err(String::from("The output needs at least one column"));
+        }
+        if self.columns.contains(&Column::Currency) && self.currency.is_none() {
+            return Err(String::from("The currency column needs a currency"));
+        }
+        Ok(())
+    }
+
+    /// An amount rounded to four places like the accounts csv, then to the precision
+    fn amount(&self, amount: f64) -> f64 {
+        let amount = round_amount(amount);
+        match self.precision {
+            Some(precision) => format!("{:.*}", precision, amount)
+                .parse()
+                .unwrap_or(amount),
+            None => amount,
+        }
+    }
+
+    /// An amount as it is written in csv, with the precision and thousands separator
+    fn format_amount(&self, amount: f64) -> String {
+        let formatted = match self.precision {
+            Some(precision) => format!("{:.*}", precision, round_amount(amount)),
+            None => format!("{:?}", round_amount(amount)),
+        };
+        match self.thousands {
+            Some(separator) => group_thousands(&formatted, separator),
+            None => formatted,
+        }
+    }
+
+    /// The value of every column for an account, named as in the header
+    fn values(&self, account: &Account, activity: &Activity) -> Vec<(&'static str, Value)> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let value = match column {
+                    Column::Client => Value::from(account.get_id()),
+                    Column::Available => Value::from(self.amount(account.get_available_amount())),
+                    Column::Held => Value::from(self.amount(account.get_held_amount())),
+                    Column::Total => Value::from(self.amount(account.get_total_amount())),
+                    Column::Locked => Value::from(account.is_frozen()),
+                    Column::TxCount => Value::from(activity.transactions),
+                    Column::OpenDisputes => Value::from(activity.open_disputes),
+                    Column::LastTx => activity.last_tx.map_or(Value::Null, Value::from),
+                    Column::Currency => self.currency.clone().map_or(Value::Null, Value::from),
+                    Column::Negative => Value::from(account.get_available_amount() < 0.0),
+                    // a chargeback is the only way an account gets locked
+                    Column::LockReason => match account.is_frozen() {
+                        true => Value::from("chargeback"),
+                        false => Value::Null,
+                    },
+                };
+                (column.name(), value)
+            })
+            .collect()
+    }
+
+    /// The csv fields of an account, with the amounts formatted
+    fn fields(&self, account: &Account, activity: &Activity) -> Vec<String> {
+        self.columns
+            .iter()
+            .zip(self.values(account, activity))
+            .map(|(column, (_, value))| match (column, value) {
+                (Column::Available, _) => self.format_amount(account.get_available_amount()),
+                (Column::Held, _) => self.format_amount(account.get_held_amount()),
+                (Column::Total, _) => self.format_amount(account.get_total_amount()),
+                (_, Value::Null) => String::new(),
+                (_, Value::String(text)) => text,
+                (_, value) => value.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Insert a separator between every three digits of the whole part of a formatted number
+///
+/// # Arguments
+///
+/// * `number` - the formatted number, such as -1234567.5
+/// * `separator` - the separator, such as a comma
+fn group_thousands(number: &str, separator: char) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", number),
+    };
+    let (whole, fraction) = match unsigned.find('.') {
+        Some(point) => unsigned.split_at(point),
+        None => (unsigned, ""),
+    };
+    let mut grouped = String::from(sign);
+    for (index, digit) in whole.chars().enumerate() {
+        if index > 0 && (whole.len() - index) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(fraction);
+    grouped
+}
+
+/// The values of an account's columns as a json object, keeping the order of the columns
+struct Row(Vec<(&'static str, Value)>);
+
+impl Serialize for Row {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in self.0.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Write the accounts with the columns and number formatting of an output config.
+/// Parquet output always has the columns of the accounts csv, so it can not be configured.
+///
+/// # Arguments
+///
+/// * `accounts` - the accounts to write along with what is on record for them, in the order they are written
+/// * `format` - the format to write them in
+/// * `config` - the columns and number formatting
+/// * `writer` - where to write them
+pub fn write_account_rows(
+    accounts: &[(Account, Activity)],
+    format: OutputFormat,
+    config: &OutputConfig,
+    mut writer: impl Write + Send,
+) -> io::Result<()> {
+    let rows = accounts
+        .iter()
+        .map(|(account, activity)| Row(config.values(account, activity)));
+    match format {
+        OutputFormat::Csv => {
+            // a thousands separator or currency can hold a comma or quote, which the csv writer quotes,
+            // and a quoted field is only read back as one right after the comma, so there are no spaces
+            let mut csv_writer = csv::Writer::from_writer(&mut writer);
+            csv_writer
+                .write_record(config.columns.iter().map(Column::name))
+                .map_err(io::Error::other)?;
+            for (account, activity) in accounts {
+                csv_writer
+                    .write_record(config.fields(account, activity))
+                    .map_err(io::Error::other)?;
+            }
+            csv_writer.flush()?;
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer(&mut writer, &rows.collect::<Vec<Row>>())?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Jsonl => {
+            for row in rows {
+                serde_json::to_writer(&mut writer, &row)?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Parquet => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "parquet output can not be configured",
+            ))
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Account, Activity};
+    use crate::input::temp_path;
+    use crate::output::{write_account_rows, write_accounts, Column, OutputConfig, OutputFormat};
+    use crate::reconcile::{read_expected, reconcile, ExpectedAccount, Tolerance};
+    use std::fs;
+
+    #[test]
+    fn test_write_accounts() {
+        let accounts = [
+            Account::with_balances(1, 1.5, 0.25, false),
+            Account::with_balances(2, 0.0, 0.0, true),
+        ];
+        let write = |format| {
+            let mut written = Vec::new();
+            write_accounts(&accounts, format, &mut written).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        assert_eq!(
+            write(OutputFormat::Csv),
+            "client, available, held, total, locked\n1, 1.5, 0.25, 1.75, false\n2, 0.0, 0.0, 0.0, true\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Jsonl),
+            "{\"client\":1,\"available\":1.5,\"held\":0.25,\"total\":1.75,\"locked\":false}\n\
+             {\"client\":2,\"available\":0.0,\"held\":0.0,\"total\":0.0,\"locked\":true}\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&write(OutputFormat::Json)).unwrap();
+        assert_eq!(json[1]["locked"], true);
+        assert_eq!(json.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_write_account_rows() {
+        let activity = Activity {
+            transactions: 3,
+            open_disputes: 1,
+            last_tx: Some(7),
+        };
+        let accounts = [
+            (Account::with_balances(1, 12345.5, 0.25, false), activity),
+            (
+                Account::with_balances(2, -2.0, 0.0, true),
+                Activity::default(),
+            ),
+        ];
+        let config = OutputConfig {
+            columns: Column::parse_list(
+                "client,total,currency,tx_count,open_disputes,last_tx,negative,lock_reason",
+            )
+            .unwrap(),
+            precision: Some(2),
+            thousands: Some(','),
+            currency: Some(String::from("EUR")),
+        };
+        let write = |format, config: &OutputConfig| {
+            let mut written = Vec::new();
+            write_account_rows(&accounts, format, config, &mut written).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+        assert_eq!(
+            write(OutputFormat::Csv, &config),
+            "client,total,currency,tx_count,open_disputes,last_tx,negative,lock_reason\n\
+             1,\"12,345.75\",EUR,3,1,7,false,\n\
+             2,-2.00,EUR,0,0,,true,chargeback\n"
+        );
+        assert_eq!(
+            write(OutputFormat::Jsonl, &config).lines().last().unwrap(),
+            "{\"client\":2,\"total\":-2.0,\"currency\":\"EUR\",\"tx_count\":0,\"open_disputes\":0,\
+             \"last_tx\":null,\"negative\":true,\"lock_reason\":\"chargeback\"}"
+        );
+
+        // the default config writes the same fields as the accounts csv
+        let plain: Vec<Account> = accounts.iter().map(|(account, _)| *account).collect();
+        let mut written = Vec::new();
+        write_accounts(&plain, OutputFormat::Csv, &mut written).unwrap();
+        assert_eq!(
+            write(OutputFormat::Csv, &OutputConfig::default()),
+            String::from_utf8(written).unwrap().replace(", ", ",")
+        );
+        // amounts too large for an i32 of ten-thousandths are written as they are
+        let large = [(
+            Account::with_balances(3, 3000000.5, 0.0, false),
+            Activity::default(),
+        )];
+        let mut written = Vec::new();
+        write_account_rows(&large, OutputFormat::Csv, &config, &mut written).unwrap();
+        assert!(String::from_utf8(written)
+            .unwrap()
+            .ends_with("3,\"3,000,000.50\",EUR,0,0,,false,\n"));
+        assert!(Column::parse_list("client,balance").is_err());
+        assert!(config.check().is_ok());
+        let no_currency = OutputConfig {
+            currency: None,
+            ..config
+        };
+        assert!(no_currency.check().is_err());
+    }
+
+    #[test]
+    fn test_read_back_account_rows() {
+        let accounts = [(
+            Account::with_balances(1, 12345.5, 0.25, true),
+            Activity::default(),
+        )];
+        let write = |config: &OutputConfig, path| {
+            let mut written = Vec::new();
+            write_account_rows(&accounts, OutputFormat::Csv, config, &mut written).unwrap();
+            fs::write(path, written).unwrap();
+        };
+        let path = &temp_path("test_read_back_account_rows.csv");
+
+        // a currency with a comma and a quote does not shift the columns after it
+        let config = OutputConfig {
+            columns: Column::parse_list("client,currency,available,held,total,locked").unwrap(),
+            precision: Some(2),
+            thousands: None,
+            currency: Some(String::from("E\"U,R")),
+        };
+        write(&config, path);
+        let expected = read_expected(path).unwrap();
+        assert_eq!(
+            expected,
+            [ExpectedAccount {
+                client: 1,
+                available: Some(12345.5),
+                held: Some(0.25),
+                total: Some(12345.75),
+                locked: Some(true),
+            }]
+        );
+        assert!(reconcile(&[accounts[0].0], &expected, &Tolerance::default()).is_empty());
+
+        // a comma thousands separator reads back as a single field
+        let config = OutputConfig {
+            thousands: Some(','),
+            ..config
+        };
+        write(&config, path);
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .unwrap();
+        let record = reader.records().next().unwrap().unwrap();
+        assert_eq!(&record[1], "E\"U,R");
+        assert_eq!(&record[4], "12,345.75");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use crate::account::{round_amount, Account};
+use crate::compression::Decompressed;
+use crate::input::InputError;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// An account as another system has it, any of the balances can be left out to not compare it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ExpectedAccount {
+    pub client: u16,
+    #[serde(default)]
+    pub available: Option<f64>,
+    #[serde(default)]
+    pub held: Option<f64>,
+    #[serde(default)]
+    pub total: Option<f64>,
+    #[serde(default)]
+    pub locked: Option<bool>,
+}
+
+/// How far apart two amounts can be and still match.
+/// Amounts match if they are within either the absolute or the relative tolerance.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tolerance {
+    /// The largest difference allowed
+    pub absolute: f64,
+    /// The largest difference allowed as a fraction of the larger amount, such as 0.001 for a tenth of a percent
+    pub relative: f64,
+}
+
+impl Tolerance {
+    /// Whether two amounts match
+    fn matches(&self, expected: f64, computed: f64) -> bool {
+        let difference = (expected - computed).abs();
+        // both amounts are rounded to four places, so anything smaller is float noise
+        difference < 0.00005
+            || difference <= self.absolute
+            || difference <= self.relative * expected.abs().max(computed.abs())
+    }
+}
+
+/// A way the computed accounts differ from the expected ones
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// An expected client has no computed account
+    Missing { client: u16 },
+    /// A computed account is not expected
+    Extra { client: u16 },
+    /// A field of an account does not match
+    Field {
+        client: u16,
+        field: &'static str,
+        expected: String,
+        computed: String,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Missing { client } => {
+                write!(f, "client {}: expected but not computed", client)
+            }
+            Mismatch::Extra { client } => write!(f, "client {}: computed but not expected", client),
+            Mismatch::Field {
+                client,
+                field,
+                expected,
+                computed,
+            } => write!(
+                f,
+                "client {}: {} expected {} but computed {}",
+                client, field, expected, computed
+            ),
+        }
+    }
+}
+
+/// Read the expected accounts from a csv with a client column and any of the available, held, total and locked columns,
+/// such as an accounts csv written by this program. It may be compressed.
+///
+/// # Arguments
+///
+/// * `path` - the expected accounts csv
+pub fn read_expected(path: &str) -> Result<Vec<ExpectedAccount>, InputError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(Decompressed::open(path)?);
+    let headers = reader.headers()?.clone();
+    let mut clients = HashSet::new();
+    let mut expected = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+        let account: ExpectedAccount =
+            record
+                .deserialize(Some(&headers))
+                .map_err(|error| InputError::Malformed {
+                    line,
+                    message: error.to_string(),
+                })?;
+        if !clients.insert(account.client) {
+            return Err(InputError::Malformed {
+                line,
+                message: format!("client {} is listed more than once", account.client),
+            });
+        }
+        expected.push(account);
+    }
+    Ok(expected)
+}
+
+/// Match the computed accounts with the expected ones by client, returning every mismatch ordered by client.
+/// Amounts are compared after rounding them the same way as the accounts csv.
+///
+/// # Arguments
+///
+/// * `computed` - the accounts the engine computed
+/// * `expected` - the accounts another system expects
+/// * `tolerance` - how far apart amounts can be and still match
+pub fn reconcile(
+    computed: &[Account],
+    expected: &[ExpectedAccount],
+    tolerance: &Tolerance,
+) -> Vec<Mismatch> {
+    let mut clients: BTreeMap<u16, (Option<&Account>, Option<&ExpectedAccount>)> = BTreeMap::new();
+    for account in computed {
+        clients.entry(account.get_id()).or_default().0 = Some(account);
+    }
+    for account in expected {
+        clients.entry(account.client).or_default().1 = Some(account);
+    }
+    let mut mismatches = Vec::new();
+    for (client, pair) in clients {
+        let (account, expected) = match pair {
+            (Some(account), Some(expected)) => (account, expected),
+            (None, _) => {
+                mismatches.push(Mismatch::Missing { client });
+                continue;
+            }
+            (_, None) => {
+                mismatches.push(Mismatch::Extra { client });
+                continue;
+            }
+        };
+        let amounts = [
+            (
+                "available",
+                expected.available,
+                account.get_available_amount(),
+            ),
+            ("held", expected.held, account.get_held_amount()),
+            ("total", expected.total, account.get_total_amount()),
+        ];
+        for (field, expected, computed) in amounts {
+            let (expected, computed) = match expected {
+                Some(expected) => (round_amount(expected), round_amount(computed)),
+                None => continue,
+            };
+            if !tolerance.matches(expected, computed) {
+                mismatches.push(Mismatch::Field {
+                    client,
+                    field,
+                    expected: format!("{:?}", expected),
+                    computed: format!("{:?}", computed),
+                });
+            }
+        }
+        if let Some(locked) = expected
+            .locked
+            .filter(|locked| *locked != account.is_frozen())
+        {
+            mismatches.push(Mismatch::Field {
+                client,
+                field: "locked",
+                expected: locked.to_string(),
+                computed: account.is_frozen().to_string(),
+            });
+        }
+    }
+    mismatches
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::Account;
+    use crate::input::temp_path;
+    use crate::reconcile::{read_expected, reconcile, Mismatch, Tolerance};
+    use std::fs;
+
+    #[test]
+    fn test_reconcile() {
+        let path = &temp_path("test_reconcile.csv");
+        fs::write(
+            path,
+            "client, available, held, total, locked\n\
+             1, 10.0, 0.0, 10.0, false\n\
+             2, 5.01, 0.0, 5.01, true\n\
+             4, 1.0, 0.0, 1.0, false\n",
+        )
+        .unwrap();
+        let expected = read_expected(path).unwrap();
+        let computed = [
+            Account::with_balances(1, 10.0, 0.0, false),
+            Account::with_balances(2, 5.0, 0.0, false),
+            Account::with_balances(3, 2.0, 0.0, false),
+        ];
+        let field = |field, expected: &str, computed: &str| Mismatch::Field {
+            client: 2,
+            field,
+            expected: expected.to_string(),
+            computed: computed.to_string(),
+        };
+        assert_eq!(
+            reconcile(&computed, &expected, &Tolerance::default()),
+            [
+                field("available", "5.01", "5.0"),
+                field("total", "5.01", "5.0"),
+                field("locked", "true", "false"),
+                Mismatch::Extra { client: 3 },
+                Mismatch::Missing { client: 4 },
+            ]
+        );
+        let tolerance = Tolerance {
+            absolute: 0.01,
+            relative: 0.0,
+        };
+        assert_eq!(reconcile(&computed, &expected, &tolerance).len(), 3);
+        let tolerance = Tolerance {
+            absolute: 0.0,
+            relative: 0.001,
+        };
+        assert_eq!(reconcile(&computed, &expected, &tolerance).len(), 5);
+
+        // only the columns that are there are compared, and every client must be listed once
+        fs::write(path, "client,total\n2,5.0\n").unwrap();
+        let expected = read_expected(path).unwrap();
+        assert_eq!(
+            reconcile(&computed[1..2], &expected, &Tolerance::default()),
+            []
+        );
+        fs::write(path, "client,total\n2,5.0\n2,5.0\n").unwrap();
+        assert!(read_expected(path).is_err());
+
+        // balances too large for an i32 of ten-thousandths are still told apart
+        fs::write(path, "client,total\n2,300000.5\n").unwrap();
+        let expected = read_expected(path).unwrap();
+        let computed = [Account::with_balances(2, 300000.0, 0.0, false)];
+        assert_eq!(
+            reconcile(&computed, &expected, &Tolerance::default()),
+            [Mismatch::Field {
+                client: 2,
+                field: "total",
+                expected: "300000.5".to_string(),
+                computed: "300000.0".to_string(),
+            }]
+        );
+    }
+}
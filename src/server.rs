@@ -0,0 +1,241 @@
+use crate::account::Transaction;
+use crate::shared::SharedAccountManager;
+use csv::{ReaderBuilder, StringRecord, Trim};
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Accept connections until the listener fails, handling each one on its own task.
+/// Every connection feeds the same accounts.
+///
+/// Each line a client sends is either a csv transaction without a header, such as `deposit, 1, 1, 1.0`,
+/// or `QUERY <client>`. Every line gets exactly one line back, in the same order:
+///  * `ok <event kind>` - the transaction was applied
+///  * `rejected <reason>` - the transaction was valid csv but was not applied
+///  * the account row, as in the output csv - the answer to a query
+///  * `error <message>` - the line could not be understood, or the queried client has no account
+///
+/// A client can instead send `SUBSCRIBE`, optionally followed by the client ids it is interested in.
+/// Once `ok subscribed` comes back, every change to those accounts is sent as a json notification line,
+/// until the client hangs up. A subscriber that falls too far behind gets `error missed <count> notifications`.
+///
+/// # Arguments
+///
+/// * `listener` - where to accept connections
+/// * `account_manager` - the accounts shared by every connection
+pub async fn serve(
+    listener: TcpListener,
+    account_manager: Arc<SharedAccountManager>,
+) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let account_manager = account_manager.clone();
+        tokio::spawn(async move {
+            // a connection that fails only affects its own client
+            let _ = handle_connection(stream, &account_manager).await;
+        });
+    }
+}
+
+/// Answer every line of a connection until the client closes it
+async fn handle_connection(
+    stream: TcpStream,
+    account_manager: &SharedAccountManager,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(clients) = line.trim().strip_prefix("SUBSCRIBE") {
+            match clients.split_whitespace().map(str::parse).collect() {
+                Ok(clients) => return stream_notifications(writer, clients, account_manager).await,
+                Err(_) => {
+                    writer
+                        .write_all(b"error SUBSCRIBE needs client ids\n")
+                        .await?;
+                    continue;
+                }
+            }
+        }
+        let mut reply = respond(&line, account_manager);
+        reply.push('\n');
+        writer.write_all(reply.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Send every notification about the given clients to a subscriber, until it hangs up
+///
+/// # Arguments
+///
+/// * `writer` - the subscriber's connection
+/// * `clients` - the clients to send notifications about, every client if empty
+/// * `account_manager` - the accounts to follow
+async fn stream_notifications(
+    mut writer: OwnedWriteHalf,
+    clients: Vec<u16>,
+    account_manager: &SharedAccountManager,
+) -> io::Result<()> {
+    let mut notifications = account_manager.subscribe();
+    writer.write_all(b"ok subscribed\n").await?;
+    loop {
+        let line = match notifications.recv().await {
+            Ok(notification) if clients.is_empty() || clients.contains(&notification.client) => {
+                serde_json::to_string(&notification)?
+            }
+            Ok(_) => continue,
+            Err(RecvError::Lagged(count)) => format!("error missed {} notifications", count),
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        writer.write_all(line.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+}
+
+/// The reply to a single line sent by a client
+///
+/// # Arguments
+///
+/// * `line` - the line, without its line ending
+/// * `account_manager` - the accounts to apply transactions to and answer queries from
+pub fn respond(line: &str, account_manager: &SharedAccountManager) -> String {
+    if let Some(client) = line.trim().strip_prefix("QUERY") {
+        return match client.trim().parse::<u16>() {
+            Ok(client) => match account_manager.find_account(client) {
+                Some(account) => account.row(),
+                None => format!("error no account for client {}", client),
+            },
+            Err(_) => String::from("error QUERY needs a client id"),
+        };
+    }
+    match parse_transaction(line) {
+        Ok(transaction) => match account_manager.process_transaction(transaction) {
+            Ok(event) => format!("ok {}", event.kind),
+            Err(error) => format!("rejected {}", error),
+        },
+        Err(error) => format!("error {}", error),
+    }
+}
+
+/// Parse a csv transaction line in the same column order as the input file
+fn parse_transaction(line: &str) -> Result<Transaction, csv::Error> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .trim(Trim::All)
+        .from_reader(line.as_bytes());
+    let mut record = StringRecord::new();
+    reader.read_record(&mut record)?;
+    record.deserialize(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::serve;
+    use crate::shared::SharedAccountManager;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Send lines over a new connection and collect one reply per line
+    async fn send(address: &str, lines: &[&str]) -> Vec<String> {
+        let stream = TcpStream::connect(address)
+            .await
+            .expect("Failed to connect");
+        let (reader, mut writer) = stream.into_split();
+        let mut replies = BufReader::new(reader).lines();
+        let mut received = Vec::new();
+        for line in lines {
+            writer.write_all(line.as_bytes()).await.unwrap();
+            writer.write_all(b"\n").await.unwrap();
+            received.push(replies.next_line().await.unwrap().expect("No reply"));
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn test_serve_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(listener, Arc::new(SharedAccountManager::new(4))));
+        let replies = send(
+            &address,
+            &[
+                "deposit, 1, 1, 10.5",
+                "withdrawal, 1, 2, 20.0",
+                "dispute, 1, 1,",
+                "QUERY 1",
+                "bogus, 1, 3, 1.0",
+                "QUERY 2",
+            ],
+        )
+        .await;
+        assert_eq!(
+            replies,
+            vec![
+                "ok deposited",
+                "rejected insufficient funds",
+                "ok held",
+                "1, 0.0, 10.5, 10.5, false",
+                "error CSV deserialize error: record 0 (line: 1, byte: 0): unknown variant `bogus`, \
+                 expected one of `deposit`, `withdrawal`, `dispute`, `resolve`, `chargeback`",
+                "error no account for client 2",
+            ]
+        );
+        // a second connection sees the same accounts
+        let replies = send(&address, &["resolve, 1, 1,", "QUERY 1"]).await;
+        assert_eq!(replies, vec!["ok released", "1, 10.5, 0.0, 10.5, false"]);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(listener, Arc::new(SharedAccountManager::new(4))));
+        let stream = TcpStream::connect(&address).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut notifications = BufReader::new(reader).lines();
+        writer.write_all(b"SUBSCRIBE 1\n").await.unwrap();
+        assert_eq!(
+            notifications.next_line().await.unwrap().as_deref(),
+            Some("ok subscribed")
+        );
+        send(
+            &address,
+            &[
+                "deposit, 2, 1, 5.0",
+                "deposit, 1, 2, 10.5",
+                "dispute, 1, 2,",
+                "chargeback, 1, 2,",
+            ],
+        )
+        .await;
+        let mut kinds = Vec::new();
+        for _ in 0..6 {
+            let line = notifications
+                .next_line()
+                .await
+                .unwrap()
+                .expect("No notification");
+            let notification: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(notification["client"], 1);
+            kinds.push(notification["kind"].as_str().unwrap().to_string());
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                "balancechanged",
+                "balancechanged",
+                "disputed",
+                "balancechanged",
+                "chargedback",
+                "locked"
+            ]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::account::Transaction;
+use crate::account_manager::AccountManager;
+use crate::snapshot::Snapshot;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// How many transactions are sent to a shard at once, sending them one by one costs more than processing them
+const BATCH_SIZE: usize = 1024;
+
+/// How many batches can be waiting for a shard before the input has to wait for it to catch up
+const QUEUED_BATCHES: usize = 16;
+
+/// The shard that owns a client's account
+///
+/// # Arguments
+///
+/// * `client` - the client id
+/// * `shards` - how many shards there are
+pub fn shard_of(client: u16, shards: usize) -> usize {
+    client as usize % shards
+}
+
+/// Processes transactions on several worker threads.
+/// Every client belongs to exactly one shard, which owns its account and transactions,
+/// so the shards never share anything and a client's transactions are processed in input order.
+/// Once the input is done, the shards are merged back into a single account manager.
+pub struct ShardedAccountManager {
+    senders: Vec<SyncSender<Vec<Transaction>>>,
+    workers: Vec<JoinHandle<AccountManager>>,
+    batches: Vec<Vec<Transaction>>,
+}
+
+/// Process batches of transactions until the sender is dropped, then hand back the accounts
+fn work(
+    mut account_manager: AccountManager,
+    receiver: Receiver<Vec<Transaction>>,
+) -> AccountManager {
+    for batch in receiver {
+        for transaction in batch {
+            // rejected transactions are ignored, as in the single threaded path
+            let _ = account_manager.process_transaction(transaction);
+        }
+    }
+    account_manager
+}
+
+impl ShardedAccountManager {
+    /// Start the worker threads, each with no accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `shards` - how many worker threads to process the transactions on, at least 1
+    /// * `audit` - whether the shards run in self-audit mode
+    pub fn new(shards: usize, audit: bool) -> Self {
+        let managers = (0..shards)
+            .map(|_| {
+                let mut account_manager = AccountManager::default();
+                account_manager.set_audit(audit);
+                account_manager
+            })
+            .collect();
+        Self::start(managers)
+    }
+
+    /// Start the worker threads with the accounts of a snapshot split between them
+    ///
+    /// # Arguments
+    ///
+    /// * `shards` - how many worker threads to process the transactions on, at least 1
+    /// * `audit` - whether the shards run in self-audit mode
+    /// * `snapshot` - the accounts and transactions to start from
+    pub fn from_snapshot(shards: usize, audit: bool, snapshot: Snapshot) -> Self {
+        let mut parts: Vec<Snapshot> = (0..shards)
+            .map(|_| Snapshot {
+                version: snapshot.version,
+                accounts: Vec::new(),
+                transactions: Vec::new(),
+                wal_seq: 0,
+                position: None,
+                event_rows: 0,
+            })
+            .collect();
+        for account in snapshot.accounts {
+            parts[shard_of(account.get_id(), shards)]
+                .accounts
+                .push(account);
+        }
+        for (transaction, state) in snapshot.transactions {
+            parts[shard_of(transaction.client, shards)]
+                .transactions
+                .push((transaction, state));
+        }
+        let managers = parts
+            .into_iter()
+            .map(|part| {
+                let mut account_manager = AccountManager::default();
+                account_manager.load_snapshot(part);
+                account_manager.set_audit(audit);
+                account_manager
+            })
+            .collect();
+        Self::start(managers)
+    }
+
+    /// Spawn one worker thread per account manager
+    fn start(managers: Vec<AccountManager>) -> Self {
+        assert!(!managers.is_empty(), "There must be at least one shard");
+        let mut senders = Vec::new();
+        let mut workers = Vec::new();
+        for account_manager in managers {
+            let (sender, receiver) = mpsc::sync_channel(QUEUED_BATCHES);
+            senders.push(sender);
+            workers.push(thread::spawn(move || work(account_manager, receiver)));
+        }
+        let batches = senders
+            .iter()
+            .map(|_| Vec::with_capacity(BATCH_SIZE))
+            .collect();
+        ShardedAccountManager {
+            senders,
+            workers,
+            batches,
+        }
+    }
+
+    /// Queue a transaction on the shard of its client.
+    /// It is processed some time later, in order with the client's other transactions.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - the transaction to process
+    pub fn process_transaction(&mut self, transaction: Transaction) {
+        let shard = shard_of(transaction.client, self.senders.len());
+        self.batches[shard].push(transaction);
+        if self.batches[shard].len() >= BATCH_SIZE {
+            self.send(shard);
+        }
+    }
+
+    /// Send the transactions queued for a shard to its worker
+    fn send(&mut self, shard: usize) {
+        let batch = std::mem::replace(&mut self.batches[shard], Vec::with_capacity(BATCH_SIZE));
+        self.senders[shard]
+            .send(batch)
+            .expect("A shard worker stopped unexpectedly");
+    }
+
+    /// Wait for every queued transaction to be processed, and merge the shards into one account manager
+    pub fn finish(mut self) -> AccountManager {
+        for shard in 0..self.senders.len() {
+            if !self.batches[shard].is_empty() {
+                self.send(shard);
+            }
+        }
+        // the workers stop once their sender is gone
+        self.senders.clear();
+        let mut merged = AccountManager::default();
+        for worker in self.workers {
+            merged.merge(worker.join().expect("A shard worker panicked"));
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::Transaction;
+    use crate::account_manager::AccountManager;
+    use crate::sharded::ShardedAccountManager;
+    use csv::{ReaderBuilder, Trim};
+
+    fn read_transactions(path: &str) -> Vec<Transaction> {
+        ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path(path)
+            .expect("Failed to read input file")
+            .deserialize()
+            .map(|result| result.expect("Failed to deserialize"))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_single_thread() {
+        let transactions = read_transactions("testfiles/biggertestfile.csv");
+        let mut single = AccountManager::default();
+        for transaction in transactions.iter() {
+            let _ = single.process_transaction(transaction.clone());
+        }
+        for shards in [1, 2, 3, 8] {
+            let mut sharded = ShardedAccountManager::new(shards, true);
+            for transaction in transactions.iter() {
+                sharded.process_transaction(transaction.clone());
+            }
+            let merged = sharded.finish();
+            assert!(merged == single, "{} shards differ", shards);
+            assert!(merged.violations().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_from_snapshot() {
+        let transactions = read_transactions("testfiles/biggertestfile.csv");
+        let (first, second) = transactions.split_at(transactions.len() / 2);
+        let mut single = AccountManager::default();
+        for transaction in first.iter() {
+            let _ = single.process_transaction(transaction.clone());
+        }
+        // continue from half way on the shards
+        let mut sharded = ShardedAccountManager::from_snapshot(4, false, single.snapshot());
+        for transaction in second.iter() {
+            let _ = single.process_transaction(transaction.clone());
+            sharded.process_transaction(transaction.clone());
+        }
+        assert!(sharded.finish() == single);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::account::{Account, Transaction, TransactionError};
+use crate::account_manager::AccountManager;
+use crate::event::Event;
+use crate::observer::Notification;
+use crate::sharded::shard_of;
+use crate::storage::TransactionState;
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// How many notifications a subscriber can fall behind before it misses some
+const QUEUED_NOTIFICATIONS: usize = 1024;
+
+/// The published balances of one client, readable without a lock.
+/// It is a sequence lock: the version is odd while the balances are being written,
+/// and a reader that sees it change while reading simply reads again.
+#[derive(Default)]
+struct Balance {
+    version: AtomicU64,
+    exists: AtomicBool,
+    available: AtomicU64,
+    held: AtomicU64,
+    frozen: AtomicBool,
+}
+
+impl Balance {
+    /// Publish an account's balances, only ever called by the holder of the account's shard lock
+    fn publish(&self, account: &Account) {
+        let version = self.version.load(Ordering::Relaxed);
+        self.version.store(version + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.exists.store(true, Ordering::Relaxed);
+        self.available
+            .store(account.get_available_amount().to_bits(), Ordering::Relaxed);
+        self.held
+            .store(account.get_held_amount().to_bits(), Ordering::Relaxed);
+        self.frozen.store(account.is_frozen(), Ordering::Relaxed);
+        self.version.store(version + 2, Ordering::Release);
+    }
+
+    /// Read the balances of a client, retrying if they were written at the same time
+    fn read(&self, client: u16) -> Option<Account> {
+        loop {
+            let before = self.version.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let exists = self.exists.load(Ordering::Relaxed);
+            let available = f64::from_bits(self.available.load(Ordering::Relaxed));
+            let held = f64::from_bits(self.held.load(Ordering::Relaxed));
+            let frozen = self.frozen.load(Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if self.version.load(Ordering::Relaxed) == before {
+                return Some(Account::with_balances(client, available, held, frozen))
+                    .filter(|_| exists);
+            }
+        }
+    }
+}
+
+/// An account manager that can be shared between threads, for many concurrent producers of transactions.
+/// The accounts are split between shards by client, each behind its own lock,
+/// so the transactions of a client are processed one at a time while other shards carry on.
+/// Every change to an account's balances is also published to a table with a slot for every client,
+/// so balances can be read at any time without taking a lock.
+/// The notifications of every shard are sent on to every subscriber, so they can follow the changes as they happen.
+pub struct SharedAccountManager {
+    shards: Vec<Mutex<AccountManager>>,
+    balances: Box<[Balance]>,
+    updates: broadcast::Sender<Notification>,
+}
+
+impl SharedAccountManager {
+    /// Create a shared account manager with no accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `shards` - how many locks to split the accounts between, at least 1
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "There must be at least one shard");
+        let updates = broadcast::channel(QUEUED_NOTIFICATIONS).0;
+        let shards = (0..shards)
+            .map(|_| {
+                let mut account_manager = AccountManager::default();
+                let updates = updates.clone();
+                account_manager.subscribe(move |notification| {
+                    // it does not matter if nobody is subscribed
+                    let _ = updates.send(notification.clone());
+                });
+                Mutex::new(account_manager)
+            })
+            .collect();
+        SharedAccountManager {
+            shards,
+            balances: (0..=u16::MAX).map(|_| Balance::default()).collect(),
+            updates,
+        }
+    }
+
+    /// Process a single transaction, waiting for any other transaction of the same shard to finish first
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to be processed
+    ///
+    /// Returns the change that was applied, or the reason the transaction was rejected
+    pub fn process_transaction(&self, transaction: Transaction) -> Result<Event, TransactionError> {
+        let client = transaction.client;
+        let mut account_manager = self.shards[shard_of(client, self.shards.len())]
+            .lock()
+            .expect("A shard was poisoned");
+        let result = account_manager.process_transaction(transaction);
+        // publish while still holding the lock, so balances are published in the order they changed
+        if let Some(account) = account_manager.find_account(&client) {
+            self.balances[client as usize].publish(&account);
+        }
+        result
+    }
+
+    /// Get a client's balances without taking a lock, if any transaction has created the account
+    pub fn find_account(&self, client: u16) -> Option<Account> {
+        self.balances[client as usize].read(client)
+    }
+
+    /// Receive every change applied to an account from now on, in the order they were applied.
+    /// A subscriber that falls too far behind misses the oldest notifications.
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.updates.subscribe()
+    }
+
+    /// Every account, ordered by client, read without taking a lock
+    pub fn accounts(&self) -> Vec<Account> {
+        (0..=u16::MAX)
+            .filter_map(|client| self.find_account(client))
+            .collect()
+    }
+
+    /// Find a stored transaction of a client, along with whether it is held because of a dispute
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - the client the transaction belongs to
+    /// * `tx` - the transaction id
+    pub fn lookup_transaction(
+        &self,
+        client: u16,
+        tx: u32,
+    ) -> Option<(Transaction, TransactionState)> {
+        self.shards[shard_of(client, self.shards.len())]
+            .lock()
+            .expect("A shard was poisoned")
+            .lookup_transaction(client, tx)
+    }
+
+    /// Merge the shards into a single account manager once all producers are done
+    pub fn into_account_manager(self) -> AccountManager {
+        let mut merged = AccountManager::default();
+        for shard in self.shards {
+            merged.merge(shard.into_inner().expect("A shard was poisoned"));
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionType};
+    use crate::account_manager::AccountManager;
+    use crate::shared::SharedAccountManager;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    const PRODUCERS: u16 = 8;
+
+    /// A mix of every transaction type, where every transaction refers back to one of the same client
+    fn generate() -> Vec<Transaction> {
+        (1..=40_000u32)
+            .map(|tx| {
+                let client = (tx % 64) as u16;
+                let earlier = tx.saturating_sub(128).max(1);
+                let (r#type, tx, amount) = match tx % 10 {
+                    0..=4 => (TransactionType::Deposit, tx, Some(10.0)),
+                    5..=6 => (TransactionType::Withdrawal, tx, Some(4.0)),
+                    7 => (TransactionType::Dispute, earlier, None),
+                    8 => (TransactionType::Resolve, earlier, None),
+                    _ => (TransactionType::Chargeback, earlier, None),
+                };
+                Transaction {
+                    r#type,
+                    client,
+                    tx,
+                    amount,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stress_matches_sequential() {
+        let transactions = generate();
+        let mut sequential = AccountManager::default();
+        for transaction in transactions.iter() {
+            let _ = sequential.process_transaction(transaction.clone());
+        }
+        let shared = SharedAccountManager::new(4);
+        thread::scope(|scope| {
+            // each producer sends the transactions of its own clients, in input order
+            for producer in 0..PRODUCERS {
+                let (shared, transactions) = (&shared, &transactions);
+                scope.spawn(move || {
+                    for transaction in transactions.iter() {
+                        if transaction.client % PRODUCERS == producer {
+                            let _ = shared.process_transaction(transaction.clone());
+                        }
+                    }
+                });
+            }
+        });
+        for client in 0..64 {
+            assert_eq!(
+                shared.find_account(client),
+                sequential.find_account(&client)
+            );
+        }
+        assert!(shared.into_account_manager() == sequential);
+    }
+
+    #[test]
+    fn test_reads_are_consistent() {
+        let shared = SharedAccountManager::new(2);
+        let deposit = Transaction {
+            r#type: TransactionType::Deposit,
+            client: 500,
+            tx: 1,
+            amount: Some(5.0),
+        };
+        assert!(shared.process_transaction(deposit).is_ok());
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..5000 {
+                    for r#type in [TransactionType::Dispute, TransactionType::Resolve] {
+                        let transaction = Transaction {
+                            r#type,
+                            client: 500,
+                            tx: 1,
+                            amount: None,
+                        };
+                        assert!(shared.process_transaction(transaction).is_ok());
+                    }
+                }
+                done.store(true, Ordering::Release);
+            });
+            // the funds move between available and held, but a read never sees half of a move
+            while !done.load(Ordering::Acquire) {
+                let account = shared.find_account(500).expect("No account");
+                assert_eq!(account.get_total_amount(), 5.0);
+            }
+        });
+        assert_eq!(shared.find_account(501), None);
+    }
+}
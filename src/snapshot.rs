@@ -0,0 +1,138 @@
+use crate::account::{Account, Transaction};
+use crate::storage::TransactionState;
+use crate::wal::InputPosition;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+
+/// The snapshot format version written by this program.
+/// Bump this whenever the layout of a snapshot changes.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// The full state of the engine: every account, every stored transaction and whether it is held
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub accounts: Vec<Account>,
+    pub transactions: Vec<(Transaction, TransactionState)>,
+    /// For write-ahead log checkpoints, the last log entry included in this snapshot
+    #[serde(default)]
+    pub wal_seq: u64,
+    /// For write-ahead log checkpoints, where to resume reading the input
+    #[serde(default)]
+    pub position: Option<InputPosition>,
+    /// For write-ahead log checkpoints, how many events the event log held
+    #[serde(default)]
+    pub event_rows: u64,
+}
+
+/// Only the version of a snapshot, whatever else it holds
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+/// The ways loading or saving a snapshot can fail
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// The snapshot was written with a format this program does not understand
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "snapshot io error: {}", error),
+            SnapshotError::Format(error) => write!(f, "snapshot format error: {}", error),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {} is not supported, expected {}",
+                version, SNAPSHOT_VERSION
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(error: serde_json::Error) -> Self {
+        SnapshotError::Format(error)
+    }
+}
+
+impl Snapshot {
+    /// Read a snapshot file, making sure it is a version we understand
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the snapshot file to read
+    pub fn load(path: &str) -> Result<Self, SnapshotError> {
+        let mut json = String::new();
+        BufReader::new(File::open(path)?).read_to_string(&mut json)?;
+        // the version is checked on its own first, as the rest of another version may not even parse
+        let version: Version = serde_json::from_str(&json)?;
+        if version.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version.version));
+        }
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Write the snapshot to a file.
+    /// It is written to a temporary file first and then renamed,
+    /// so a crash while saving never leaves a partial snapshot behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - where to write the snapshot
+    pub fn save(&self, path: &str) -> Result<(), SnapshotError> {
+        let temp_path = format!("{}.tmp", path);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::temp_path;
+    use crate::snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION};
+    use std::fs;
+
+    #[test]
+    fn test_load_version() {
+        let path = &temp_path("test_load_version.json");
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            accounts: Vec::new(),
+            transactions: Vec::new(),
+            wal_seq: 3,
+            position: None,
+            event_rows: 0,
+        };
+        snapshot.save(path).unwrap();
+        assert_eq!(Snapshot::load(path).unwrap(), snapshot);
+        // an older layout is reported by its version rather than by what no longer parses
+        fs::write(path, r#"{"version": 1, "accounts": {"1": [1.0, 0.0]}}"#).unwrap();
+        assert!(matches!(
+            Snapshot::load(path),
+            Err(SnapshotError::UnsupportedVersion(1))
+        ));
+        fs::write(path, r#"{"accounts": []}"#).unwrap();
+        assert!(matches!(
+            Snapshot::load(path),
+            Err(SnapshotError::Format(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}
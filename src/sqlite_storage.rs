@@ -0,0 +1,372 @@
+use crate::account::{Account, Transaction};
+use crate::event::{unix_time, Event, TimedEvent};
+use crate::storage::{Storage, TransactionState};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// The tables of the ledger, created when a new database is opened
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS accounts (
+    client INTEGER PRIMARY KEY,
+    available REAL NOT NULL,
+    held REAL NOT NULL,
+    frozen INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    client INTEGER NOT NULL,
+    tx INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    amount REAL,
+    state TEXT NOT NULL,
+    PRIMARY KEY (client, tx, state)
+);
+CREATE TABLE IF NOT EXISTS events (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    client INTEGER NOT NULL,
+    tx INTEGER NOT NULL,
+    amount REAL NOT NULL,
+    time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_by_client ON events (client, seq);
+";
+
+/// A storage that keeps the ledger in a SQLite database file,
+/// so it can be built up over many runs and queried with SQL.
+/// Every input transaction is applied inside its own database transaction.
+///
+/// Database errors can not be recovered from in the middle of applying a transaction, so they panic.
+/// Anything not yet committed is rolled back by SQLite, so the ledger is never partially updated.
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+/// The name serde gives a unit enum variant, which is also how it is stored in the database
+fn name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => panic!("Only unit enum variants have a name"),
+    }
+}
+
+/// Parse a unit enum variant stored by its name
+fn parse<T: DeserializeOwned>(name: String) -> rusqlite::Result<T> {
+    serde_json::from_value(serde_json::Value::String(name))
+        .map_err(|error| rusqlite::Error::ToSqlConversionFailure(Box::new(error)))
+}
+
+/// Read a transaction and its state from a row of client, tx, type, amount, state
+fn transaction_from_row(row: &Row) -> rusqlite::Result<(Transaction, TransactionState)> {
+    let transaction = Transaction {
+        client: row.get(0)?,
+        tx: row.get(1)?,
+        r#type: parse(row.get(2)?)?,
+        amount: row.get(3)?,
+    };
+    Ok((transaction, parse(row.get(4)?)?))
+}
+
+/// Read an account from a row of client, available, held, frozen
+fn account_from_row(row: &Row) -> rusqlite::Result<Account> {
+    Ok(Account::with_balances(
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+    ))
+}
+
+impl SqliteStorage {
+    /// Open a ledger database, creating it and its tables if they do not exist
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the database file, or ":memory:" for a database that is not saved
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        // a write-ahead journal is much faster for many small transactions
+        connection.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(SqliteStorage { connection })
+    }
+
+    /// The applied events of some clients along with when they were applied, in the order they were applied
+    ///
+    /// # Arguments
+    ///
+    /// * `clients` - the clients whose events to read, or every client if empty
+    pub fn history(&self, clients: &[u16]) -> rusqlite::Result<Vec<TimedEvent>> {
+        let filter = match clients.len() {
+            0 => String::new(),
+            count => format!("WHERE client IN ({})", vec!["?"; count].join(", ")),
+        };
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT kind, client, tx, amount, time FROM events {} ORDER BY seq",
+            filter
+        ))?;
+        let history = statement.query_map(params_from_iter(clients), |row| {
+            Ok(TimedEvent {
+                event: Event {
+                    kind: parse(row.get(0)?)?,
+                    client: row.get(1)?,
+                    tx: row.get(2)?,
+                    amount: row.get(3)?,
+                },
+                time: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+        history.collect()
+    }
+
+    /// Run a statement that changes the ledger
+    fn execute(&self, sql: &str, params: impl rusqlite::Params) -> usize {
+        self.connection
+            .prepare_cached(sql)
+            .and_then(|mut statement| statement.execute(params))
+            .expect("Failed to update the ledger database")
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get_account(&self, client: u16) -> Option<Account> {
+        self.connection
+            .prepare_cached(
+                "SELECT client, available, held, frozen FROM accounts WHERE client = ?1",
+            )
+            .and_then(|mut statement| statement.query_row([client], account_from_row).optional())
+            .expect("Failed to read the ledger database")
+    }
+
+    fn put_account(&mut self, account: Account) {
+        self.execute(
+            "INSERT OR REPLACE INTO accounts (client, available, held, frozen) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                account.get_id(),
+                account.get_available_amount(),
+                account.get_held_amount(),
+                account.is_frozen()
+            ],
+        );
+    }
+
+    fn accounts(&self) -> Vec<Account> {
+        self.connection
+            .prepare_cached("SELECT client, available, held, frozen FROM accounts")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], account_from_row)?
+                    .collect::<rusqlite::Result<Vec<Account>>>()
+            })
+            .expect("Failed to read the ledger database")
+    }
+
+    fn lookup_transaction(&self, client: u16, tx: u32) -> Option<(Transaction, TransactionState)> {
+        // settled sorts before held, matching the other storages
+        self.connection
+            .prepare_cached(
+                "SELECT client, tx, type, amount, state FROM transactions
+                 WHERE client = ?1 AND tx = ?2 ORDER BY state = 'held' LIMIT 1",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_row(params![client, tx], transaction_from_row)
+                    .optional()
+            })
+            .expect("Failed to read the ledger database")
+    }
+
+    fn insert_transaction(&mut self, transaction: Transaction) {
+        self.execute(
+            "INSERT OR REPLACE INTO transactions (client, tx, type, amount, state)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                transaction.client,
+                transaction.tx,
+                name(&transaction.r#type),
+                transaction.amount,
+                name(&TransactionState::Settled)
+            ],
+        );
+    }
+
+    fn move_transaction(
+        &mut self,
+        client: u16,
+        tx: u32,
+        from: TransactionState,
+        to: Option<TransactionState>,
+    ) -> Option<Transaction> {
+        let (transaction, _) = self
+            .connection
+            .prepare_cached(
+                "SELECT client, tx, type, amount, state FROM transactions
+                 WHERE client = ?1 AND tx = ?2 AND state = ?3",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_row(params![client, tx, name(&from)], transaction_from_row)
+                    .optional()
+            })
+            .expect("Failed to read the ledger database")?;
+        match to {
+            Some(to) => self.execute(
+                "UPDATE OR REPLACE transactions SET state = ?4
+                 WHERE client = ?1 AND tx = ?2 AND state = ?3",
+                params![client, tx, name(&from), name(&to)],
+            ),
+            None => self.execute(
+                "DELETE FROM transactions WHERE client = ?1 AND tx = ?2 AND state = ?3",
+                params![client, tx, name(&from)],
+            ),
+        };
+        Some(transaction)
+    }
+
+    fn client_transactions(&self, client: u16) -> Vec<(Transaction, TransactionState)> {
+        self.connection
+            .prepare_cached(
+                "SELECT client, tx, type, amount, state FROM transactions WHERE client = ?1",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map([client], transaction_from_row)?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .expect("Failed to read the ledger database")
+    }
+
+    fn held_transactions(&self, client: u16) -> Vec<Transaction> {
+        self.connection
+            .prepare_cached(
+                "SELECT client, tx, type, amount, state FROM transactions
+                 WHERE client = ?1 AND state = ?2",
+            )
+            .and_then(|mut statement| {
+                statement
+                    .query_map(
+                        params![client, name(&TransactionState::Held)],
+                        transaction_from_row,
+                    )?
+                    .map(|row| row.map(|(transaction, _)| transaction))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .expect("Failed to read the ledger database")
+    }
+
+    fn begin(&mut self) {
+        self.execute("BEGIN", []);
+    }
+
+    fn commit(&mut self) {
+        self.execute("COMMIT", []);
+    }
+
+    fn record_event(&mut self, event: &Event) {
+        self.execute(
+            "INSERT INTO events (kind, client, tx, amount, time) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                name(&event.kind),
+                event.client,
+                event.tx,
+                event.amount,
+                unix_time() as i64
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionType};
+    use crate::account_manager::AccountManager;
+    use crate::input::temp_path;
+    use crate::sqlite_storage::SqliteStorage;
+    use crate::storage::{MemoryStorage, Storage, TransactionState};
+    use csv::{ReaderBuilder, Trim};
+    use std::fs;
+
+    #[test]
+    fn test_matches_memory_storage() {
+        let mut memory = AccountManager::new(MemoryStorage::default());
+        let mut sqlite = AccountManager::new(SqliteStorage::open(":memory:").unwrap());
+        let mut csv_reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path("testfiles/biggertestfile.csv")
+            .expect("Failed to read input file testfiles/biggertestfile.csv");
+        for result in csv_reader.deserialize::<Transaction>() {
+            let transaction = result.expect("Failed to deserialize");
+            assert_eq!(
+                memory.process_transaction(transaction.clone()),
+                sqlite.process_transaction(transaction)
+            );
+        }
+        assert_eq!(memory.snapshot().accounts, sqlite.snapshot().accounts);
+        assert_eq!(
+            memory.snapshot().transactions,
+            sqlite.snapshot().transactions
+        );
+    }
+
+    #[test]
+    fn test_load_snapshot() {
+        let mut memory = AccountManager::new(MemoryStorage::default());
+        let mut csv_reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path("testfiles/biggertestfile.csv")
+            .expect("Failed to read input file testfiles/biggertestfile.csv");
+        for result in csv_reader.deserialize::<Transaction>() {
+            let _ = memory.process_transaction(result.expect("Failed to deserialize"));
+        }
+        let mut sqlite = AccountManager::new(SqliteStorage::open(":memory:").unwrap());
+        sqlite.load_snapshot(memory.snapshot());
+        assert_eq!(memory.snapshot(), sqlite.snapshot());
+        // the snapshot was committed, so processing can carry on
+        let deposit = Transaction {
+            r#type: TransactionType::Deposit,
+            client: 1,
+            tx: 1000,
+            amount: Some(1.0),
+        };
+        assert_eq!(
+            memory.process_transaction(deposit.clone()),
+            sqlite.process_transaction(deposit)
+        );
+    }
+
+    #[test]
+    fn test_persists_between_runs() {
+        let path = &temp_path("test_persists_between_runs.db");
+        let _ = fs::remove_file(path);
+        let deposit = Transaction {
+            r#type: TransactionType::Deposit,
+            client: 3,
+            tx: 7,
+            amount: Some(12.5),
+        };
+        let mut account_manager = AccountManager::new(SqliteStorage::open(path).unwrap());
+        assert!(account_manager.process_transaction(deposit.clone()).is_ok());
+        drop(account_manager);
+        // a later run can dispute the deposit from the earlier one
+        let mut account_manager = AccountManager::new(SqliteStorage::open(path).unwrap());
+        let dispute = Transaction {
+            r#type: TransactionType::Dispute,
+            client: 3,
+            tx: 7,
+            amount: None,
+        };
+        assert!(account_manager.process_transaction(dispute).is_ok());
+        let storage = SqliteStorage::open(path).unwrap();
+        assert_eq!(
+            storage.lookup_transaction(3, 7),
+            Some((deposit.clone(), TransactionState::Held))
+        );
+        assert_eq!(storage.held_transactions(3), vec![deposit]);
+        assert_eq!(storage.history(&[]).unwrap().len(), 2);
+        assert_eq!(storage.history(&[3]).unwrap().len(), 2);
+        assert!(storage.history(&[4]).unwrap().is_empty());
+        let account = storage.get_account(3).expect("No account");
+        assert_eq!(account.get_held_amount(), 12.5);
+        assert_eq!(account.get_available_amount(), 0.0);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::account::{round_amount, Account, TransactionType};
+use crate::event::{EventKind, TimedEvent};
+use crate::history::{applies, format_time, Period};
+use crate::storage::MemoryStorage;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// The formats a statement can be written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatementFormat {
+    /// One csv row per line of every statement
+    #[default]
+    Csv,
+    /// Plain text with aligned columns
+    Text,
+    /// An html document with a table per statement
+    Html,
+}
+
+impl FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "csv" => Ok(StatementFormat::Csv),
+            "text" => Ok(StatementFormat::Text),
+            "html" => Ok(StatementFormat::Html),
+            _ => Err(format!(
+                "Unknown statement format {}, expected csv, text or html",
+                name
+            )),
+        }
+    }
+}
+
+/// A transaction applied to the account, with the balances right after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementLine {
+    /// When it was applied, in seconds since the unix epoch
+    pub time: u64,
+    pub r#type: TransactionType,
+    /// The deposit or withdrawal, or the deposit that was disputed
+    pub tx: u32,
+    pub amount: f64,
+    pub balances: Account,
+}
+
+/// Every transaction applied to a client's account in a period, with running balances
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub client: u16,
+    /// The balances before the first line
+    pub opening: Account,
+    pub lines: Vec<StatementLine>,
+    /// The balances after the last line
+    pub closing: Account,
+}
+
+/// The transaction that applied an event
+fn transaction_type(kind: &EventKind) -> TransactionType {
+    match kind {
+        EventKind::Deposited => TransactionType::Deposit,
+        EventKind::Withdrew => TransactionType::Withdrawal,
+        EventKind::Held => TransactionType::Dispute,
+        EventKind::Released => TransactionType::Resolve,
+        EventKind::ChargedBack => TransactionType::Chargeback,
+    }
+}
+
+impl Statement {
+    /// Build a client's statement by replaying the history from the start, so the opening balances are right
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - every applied event, in the order they were applied
+    /// * `client` - the client to build the statement of
+    /// * `period` - the part of the history the statement covers
+    pub fn build(history: &[TimedEvent], client: u16, period: &Period) -> Result<Self, String> {
+        let mut account = Account::new(client);
+        let mut storage = MemoryStorage::default();
+        let mut opening = account;
+        let mut lines = Vec::new();
+        let (mut seen, mut started, mut ended) = (false, period.from_tx.is_none(), false);
+        for timed in history.iter().filter(|timed| timed.event.client == client) {
+            seen = true;
+            if period.from_tx.is_some_and(|tx| applies(timed, tx)) {
+                started = true;
+            }
+            if period.after(timed) {
+                break;
+            }
+            account.apply_event(&timed.event, &mut storage);
+            if period.before(timed, started) {
+                opening = account;
+                continue;
+            }
+            lines.push(StatementLine {
+                time: timed.time,
+                r#type: transaction_type(&timed.event.kind),
+                tx: timed.event.tx,
+                amount: timed.event.amount,
+                balances: account,
+            });
+            if period.to_tx.is_some_and(|tx| applies(timed, tx)) {
+                ended = true;
+                break;
+            }
+        }
+        if !seen {
+            return Err(format!("Client {} has no history", client));
+        }
+        let missing = match (period.from_tx, period.to_tx) {
+            (Some(tx), _) if !started => Some(tx),
+            (_, Some(tx)) if !ended && period.to_time.is_none() => Some(tx),
+            _ => None,
+        };
+        if let Some(tx) = missing {
+            return Err(format!(
+                "Client {} has no deposit or withdrawal {} in the period",
+                client, tx
+            ));
+        }
+        let closing = lines.last().map_or(opening, |line| line.balances);
+        Ok(Statement {
+            client,
+            opening,
+            lines,
+            closing,
+        })
+    }
+}
+
+/// Build the statements of several clients, ordered by client
+///
+/// # Arguments
+///
+/// * `history` - every applied event, in the order they were applied
+/// * `clients` - the clients to build statements of, or every client in the history if empty
+/// * `period` - the part of the history the statements cover
+pub fn statements(
+    history: &[TimedEvent],
+    clients: &[u16],
+    period: &Period,
+) -> Result<Vec<Statement>, String> {
+    // split the history by client in one pass, rather than going through all of it for every client
+    let mut by_client: BTreeMap<u16, Vec<TimedEvent>> =
+        clients.iter().map(|client| (*client, Vec::new())).collect();
+    for timed in history {
+        match by_client.get_mut(&timed.event.client) {
+            Some(events) => events.push(timed.clone()),
+            None if clients.is_empty() => {
+                by_client.insert(timed.event.client, vec![timed.clone()]);
+            }
+            None => {}
+        }
+    }
+    by_client
+        .into_iter()
+        .map(|(client, history)| Statement::build(&history, client, period))
+        .collect()
+}
+
+/// The fields of every row of a statement: time, type, tx, amount, available, held, total, locked.
+/// The opening and closing balances are rows of their own, without a time, tx or amount.
+fn rows(statement: &Statement) -> Vec<[String; 8]> {
+    let balances = |description: &str, account: &Account| {
+        [
+            String::new(),
+            description.to_string(),
+            String::new(),
+            String::new(),
+            format!("{:?}", round_amount(account.get_available_amount())),
+            format!("{:?}", round_amount(account.get_held_amount())),
+            format!("{:?}", round_amount(account.get_total_amount())),
+            format!("{:?}", account.is_frozen()),
+        ]
+    };
+    let mut rows = vec![balances("opening balance", &statement.opening)];
+    for line in statement.lines.iter() {
+        let [_, _, _, _, available, held, total, locked] = balances("", &line.balances);
+        rows.push([
+            format_time(line.time),
+            line.r#type.to_string(),
+            line.tx.to_string(),
+            format!("{:?}", round_amount(line.amount)),
+            available,
+            held,
+            total,
+            locked,
+        ]);
+    }
+    rows.push(balances("closing balance", &statement.closing));
+    rows
+}
+
+/// The column names of a statement
+const HEADER: [&str; 8] = [
+    "time",
+    "type",
+    "tx",
+    "amount",
+    "available",
+    "held",
+    "total",
+    "locked",
+];
+
+/// Write statements, one after another
+///
+/// # Arguments
+///
+/// * `statements` - the statements to write
+/// * `format` - the format to write them in
+/// * `writer` - where to write them
+pub fn write_statements(
+    statements: &[Statement],
+    format: StatementFormat,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    match format {
+        StatementFormat::Csv => {
+            writeln!(writer, "client, {}", HEADER.join(", "))?;
+            for statement in statements {
+                for row in rows(statement) {
+                    writeln!(writer, "{}, {}", statement.client, row.join(", "))?;
+                }
+            }
+        }
+        StatementFormat::Text => {
+            for (index, statement) in statements.iter().enumerate() {
+                if index > 0 {
+                    writeln!(writer)?;
+                }
+                writeln!(writer, "Statement for client {}", statement.client)?;
+                writeln!(writer)?;
+                for row in [HEADER.map(String::from)]
+                    .into_iter()
+                    .chain(rows(statement))
+                {
+                    let [time, r#type, tx, amount, available, held, total, locked] = row;
+                    let line = format!(
+                        "{:<19}  {:<15}  {:>10}  {:>14}  {:>14}  {:>14}  {:>14}  {:<6}",
+                        time, r#type, tx, amount, available, held, total, locked
+                    );
+                    writeln!(writer, "{}", line.trim_end())?;
+                }
+            }
+        }
+        StatementFormat::Html => {
+            writeln!(writer, "<!DOCTYPE html>")?;
+            writeln!(writer, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+            writeln!(writer, "<title>Statements</title>\n</head>\n<body>")?;
+            for statement in statements {
+                writeln!(writer, "<h2>Statement for client {}</h2>", statement.client)?;
+                writeln!(writer, "<table>")?;
+                let cells: Vec<String> = HEADER
+                    .iter()
+                    .map(|name| format!("<th>{}</th>", name))
+                    .collect();
+                writeln!(writer, "<tr>{}</tr>", cells.concat())?;
+                // every field is a number, a time or a fixed word, so nothing needs escaping
+                for row in rows(statement) {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .map(|field| format!("<td>{}</td>", field))
+                        .collect();
+                    writeln!(writer, "<tr>{}</tr>", cells.concat())?;
+                }
+                writeln!(writer, "</table>")?;
+            }
+            writeln!(writer, "</body>\n</html>")?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::{Event, EventKind, TimedEvent};
+    use crate::history::Period;
+    use crate::statement::{statements, write_statements, Statement, StatementFormat};
+
+    /// A history of two clients, with client 1 disputing and charging back its first deposit
+    fn history() -> Vec<TimedEvent> {
+        let timed = |kind, client, tx, amount, time| TimedEvent {
+            event: Event {
+                kind,
+                client,
+                tx,
+                amount,
+            },
+            time,
+        };
+        vec![
+            timed(EventKind::Deposited, 1, 1, 10.0, 100),
+            timed(EventKind::Deposited, 2, 2, 5.0, 100),
+            timed(EventKind::Deposited, 1, 3, 2.5, 200),
+            timed(EventKind::Withdrew, 1, 4, 1.0, 300),
+            timed(EventKind::Held, 1, 1, 10.0, 400),
+            timed(EventKind::ChargedBack, 1, 1, 10.0, 500),
+        ]
+    }
+
+    #[test]
+    fn test_statement() {
+        let history = history();
+        let period = Period {
+            from_tx: Some(3),
+            ..Period::default()
+        };
+        let statement = Statement::build(&history, 1, &period).unwrap();
+        assert_eq!(statement.opening.get_available_amount(), 10.0);
+        assert_eq!(statement.lines.len(), 4);
+        assert_eq!(statement.lines[2].balances.get_held_amount(), 10.0);
+        assert_eq!(statement.closing.get_total_amount(), 1.5);
+        assert!(statement.closing.is_frozen());
+
+        // the time period ends before the chargeback
+        let period = Period {
+            from_time: Some(200),
+            to_time: Some(500),
+            ..Period::default()
+        };
+        let mut written = Vec::new();
+        let written_statements = statements(&history, &[1], &period).unwrap();
+        write_statements(&written_statements, StatementFormat::Csv, &mut written).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "client, time, type, tx, amount, available, held, total, locked\n\
+             1, , opening balance, , , 10.0, 0.0, 10.0, false\n\
+             1, 1970-01-01 00:03:20, deposit, 3, 2.5, 12.5, 0.0, 12.5, false\n\
+             1, 1970-01-01 00:05:00, withdrawal, 4, 1.0, 11.5, 0.0, 11.5, false\n\
+             1, 1970-01-01 00:06:40, dispute, 1, 10.0, 1.5, 10.0, 11.5, false\n\
+             1, , closing balance, , , 1.5, 10.0, 11.5, false\n"
+        );
+
+        // a dispute is not the transaction it refers to
+        let period = Period {
+            to_tx: Some(1),
+            ..Period::default()
+        };
+        let statement = Statement::build(&history, 1, &period).unwrap();
+        assert_eq!(statement.lines.len(), 1);
+        let period = Period {
+            from_tx: Some(2),
+            ..Period::default()
+        };
+        assert!(Statement::build(&history, 1, &period).is_err());
+        assert!(Statement::build(&history, 3, &Period::default()).is_err());
+
+        // every client in the history, ordered by client, and each with only its own lines
+        let all = statements(&history, &[], &Period::default()).unwrap();
+        let clients: Vec<u16> = all.iter().map(|statement| statement.client).collect();
+        assert_eq!(clients, [1, 2]);
+        assert_eq!(all[1].lines.len(), 1);
+        assert!(statements(&history, &[2, 3], &Period::default()).is_err());
+
+        // amounts too large for an i32 of ten-thousandths are written as they are
+        let mut large = history[..1].to_vec();
+        large[0].event.amount = 500000.25;
+        let mut written = Vec::new();
+        let written_statements = statements(&large, &[1], &Period::default()).unwrap();
+        write_statements(&written_statements, StatementFormat::Csv, &mut written).unwrap();
+        assert!(String::from_utf8(written)
+            .unwrap()
+            .ends_with("1, , closing balance, , , 500000.25, 0.0, 500000.25, false\n"));
+    }
+}
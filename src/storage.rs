@@ -0,0 +1,212 @@
+use crate::account::{Account, Transaction};
+use crate::event::Event;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Where a stored transaction currently is
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionState {
+    /// Applied and not under dispute
+    Settled,
+    /// Under dispute, its amount is held
+    Held,
+}
+
+/// Where the accounts and their past transactions are kept.
+/// Accounts are small and handed out by value: get one, change it, then put it back.
+/// Transactions are only stored for the client they belong to.
+pub trait Storage {
+    /// Get a client's account, if it exists
+    fn get_account(&self, client: u16) -> Option<Account>;
+
+    /// Create or replace a client's account
+    fn put_account(&mut self, account: Account);
+
+    /// Every account, in no particular order
+    fn accounts(&self) -> Vec<Account>;
+
+    /// Find a stored transaction, along with whether it is held.
+    /// If a transaction is somehow stored as both, the settled one is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - the client the transaction belongs to
+    /// * `tx` - the transaction id
+    fn lookup_transaction(&self, client: u16, tx: u32) -> Option<(Transaction, TransactionState)>;
+
+    /// Store a newly applied transaction as settled, replacing any settled transaction with the same id
+    fn insert_transaction(&mut self, transaction: Transaction);
+
+    /// Move a stored transaction from one state to another, or remove it if `to` is None.
+    /// Returns the transaction if it was found in the `from` state.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - the client the transaction belongs to
+    /// * `tx` - the transaction id
+    /// * `from` - the state the transaction is expected to be in
+    /// * `to` - the new state, or None to remove it
+    fn move_transaction(
+        &mut self,
+        client: u16,
+        tx: u32,
+        from: TransactionState,
+        to: Option<TransactionState>,
+    ) -> Option<Transaction>;
+
+    /// Every stored transaction of a client along with its state, in no particular order
+    fn client_transactions(&self, client: u16) -> Vec<(Transaction, TransactionState)>;
+
+    /// Every held transaction of a client, in no particular order
+    fn held_transactions(&self, client: u16) -> Vec<Transaction>;
+
+    /// Called before a transaction is applied.
+    /// Every change up to the matching commit must be kept all together or not at all.
+    fn begin(&mut self) {}
+
+    /// Called once a transaction has been applied
+    fn commit(&mut self) {}
+
+    /// Keep an applied event as history, storages that have no use for it can ignore it
+    fn record_event(&mut self, _event: &Event) {}
+}
+
+/// The default storage, keeping everything in hashmaps
+#[derive(Debug, Default, PartialEq)]
+pub struct MemoryStorage {
+    accounts: HashMap<u16, Account>,
+    transactions: HashMap<u16, HashMap<u32, Transaction>>,
+    held_transactions: HashMap<u16, HashMap<u32, Transaction>>,
+}
+
+impl MemoryStorage {
+    /// The map holding a client's transactions in the given state
+    fn map(&self, client: u16, state: TransactionState) -> Option<&HashMap<u32, Transaction>> {
+        match state {
+            TransactionState::Settled => self.transactions.get(&client),
+            TransactionState::Held => self.held_transactions.get(&client),
+        }
+    }
+
+    /// The map holding a client's transactions in the given state, created if needed
+    fn map_mut(&mut self, client: u16, state: TransactionState) -> &mut HashMap<u32, Transaction> {
+        match state {
+            TransactionState::Settled => self.transactions.entry(client).or_default(),
+            TransactionState::Held => self.held_transactions.entry(client).or_default(),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get_account(&self, client: u16) -> Option<Account> {
+        self.accounts.get(&client).copied()
+    }
+
+    fn put_account(&mut self, account: Account) {
+        self.accounts.insert(account.get_id(), account);
+    }
+
+    fn accounts(&self) -> Vec<Account> {
+        self.accounts.values().copied().collect()
+    }
+
+    fn lookup_transaction(&self, client: u16, tx: u32) -> Option<(Transaction, TransactionState)> {
+        [TransactionState::Settled, TransactionState::Held]
+            .into_iter()
+            .find_map(|state| {
+                self.map(client, state)
+                    .and_then(|transactions| transactions.get(&tx))
+                    .map(|transaction| (transaction.clone(), state))
+            })
+    }
+
+    fn insert_transaction(&mut self, transaction: Transaction) {
+        self.map_mut(transaction.client, TransactionState::Settled)
+            .insert(transaction.tx, transaction);
+    }
+
+    fn move_transaction(
+        &mut self,
+        client: u16,
+        tx: u32,
+        from: TransactionState,
+        to: Option<TransactionState>,
+    ) -> Option<Transaction> {
+        let transaction = self.map_mut(client, from).remove(&tx)?;
+        if let Some(to) = to {
+            self.map_mut(client, to).insert(tx, transaction.clone());
+        }
+        Some(transaction)
+    }
+
+    fn client_transactions(&self, client: u16) -> Vec<(Transaction, TransactionState)> {
+        let mut transactions = Vec::new();
+        for state in [TransactionState::Settled, TransactionState::Held] {
+            if let Some(map) = self.map(client, state) {
+                for transaction in map.values() {
+                    transactions.push((transaction.clone(), state));
+                }
+            }
+        }
+        transactions
+    }
+
+    fn held_transactions(&self, client: u16) -> Vec<Transaction> {
+        self.map(client, TransactionState::Held)
+            .map(|map| map.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::account::{Transaction, TransactionType};
+    use crate::storage::{MemoryStorage, Storage, TransactionState};
+
+    #[test]
+    fn test_move_transaction() {
+        let mut storage = MemoryStorage::default();
+        let deposit = Transaction {
+            r#type: TransactionType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some(10.0),
+        };
+        storage.insert_transaction(deposit.clone());
+        assert_eq!(
+            storage.lookup_transaction(1, 1),
+            Some((deposit.clone(), TransactionState::Settled))
+        );
+        // transactions are only found for their own client
+        assert_eq!(storage.lookup_transaction(2, 1), None);
+        // it is not held, so it can not be released
+        assert_eq!(
+            storage.move_transaction(
+                1,
+                1,
+                TransactionState::Held,
+                Some(TransactionState::Settled)
+            ),
+            None
+        );
+        assert!(storage
+            .move_transaction(
+                1,
+                1,
+                TransactionState::Settled,
+                Some(TransactionState::Held)
+            )
+            .is_some());
+        assert_eq!(
+            storage.client_transactions(1),
+            vec![(deposit.clone(), TransactionState::Held)]
+        );
+        assert_eq!(storage.held_transactions(1), vec![deposit.clone()]);
+        assert_eq!(
+            storage.move_transaction(1, 1, TransactionState::Held, None),
+            Some(deposit)
+        );
+        assert!(storage.client_transactions(1).is_empty());
+    }
+}
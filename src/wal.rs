@@ -0,0 +1,226 @@
+use crate::event::Event;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Where to resume reading an input csv, mirrors csv::Position so it can be serialized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct InputPosition {
+    pub byte: u64,
+    pub line: u64,
+    pub record: u64,
+}
+
+impl From<&csv::Position> for InputPosition {
+    fn from(position: &csv::Position) -> Self {
+        InputPosition {
+            byte: position.byte(),
+            line: position.line(),
+            record: position.record(),
+        }
+    }
+}
+
+impl From<InputPosition> for csv::Position {
+    fn from(position: InputPosition) -> Self {
+        let mut csv_position = csv::Position::new();
+        csv_position
+            .set_byte(position.byte)
+            .set_line(position.line)
+            .set_record(position.record);
+        csv_position
+    }
+}
+
+/// A single applied transaction, as recorded in the write-ahead log
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WalEntry {
+    /// Increases by one for every entry, and keeps increasing across checkpoints
+    pub seq: u64,
+    /// Where the input continues after the transaction that produced this entry
+    pub position: InputPosition,
+    /// The input line or record number of the transaction, for its journal row
+    pub line: u64,
+    /// When the transaction was applied, in seconds since the unix epoch, for its event log row
+    pub time: u64,
+    pub event: Event,
+}
+
+/// An append-only log of applied transactions, one json entry per line.
+/// Every entry is synced to disk before append returns,
+/// so anything that was acknowledged after an append survives a crash.
+pub struct WriteAheadLog {
+    file: File,
+    next_seq: u64,
+}
+
+/// The checkpoint snapshot that belongs to a write-ahead log
+///
+/// # Arguments
+///
+/// * `wal_path` - the path of the write-ahead log
+pub fn checkpoint_path(wal_path: &str) -> String {
+    format!("{}.snapshot", wal_path)
+}
+
+/// Cut off a last line that a crash left half written, so the rows appended after it start on a line of their own.
+/// The journal and event log are appended to when resuming, and only end on a complete row once synced.
+///
+/// # Arguments
+///
+/// * `path` - the file to cut, nothing happens if it does not exist
+pub fn drop_partial_line(path: &str) -> io::Result<()> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    let complete = contents
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    file.set_len(complete as u64)
+}
+
+impl WriteAheadLog {
+    /// Open a write-ahead log, creating it if it does not exist,
+    /// and return the entries that are not already covered by a checkpoint.
+    /// A partially written last entry from a crash is discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the write-ahead log file
+    /// * `last_seq` - the last entry included in the checkpoint the state was restored from
+    pub fn open(path: &str, last_seq: u64) -> io::Result<(Self, Vec<WalEntry>)> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut entries = Vec::new();
+        let mut valid_length = 0;
+        let mut next_seq = last_seq + 1;
+        let mut reader = BufReader::new(&file);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            // a line without a newline was cut off in the middle of a write
+            if !line.ends_with('\n') {
+                break;
+            }
+            let entry: WalEntry = match serde_json::from_str(&line) {
+                Ok(entry) => entry,
+                Err(_) => break,
+            };
+            valid_length += line.len() as u64;
+            next_seq = next_seq.max(entry.seq + 1);
+            if entry.seq > last_seq {
+                entries.push(entry);
+            }
+            line.clear();
+        }
+        // drop anything after the last complete entry so new entries are not appended to garbage
+        file.set_len(valid_length)?;
+        Ok((WriteAheadLog { file, next_seq }, entries))
+    }
+
+    /// Durably record an applied transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - where the input continues after the transaction
+    /// * `line` - the input line or record number of the transaction
+    /// * `time` - when the transaction was applied, in seconds since the unix epoch
+    /// * `event` - the change the transaction applied
+    pub fn append(
+        &mut self,
+        position: InputPosition,
+        line: u64,
+        time: u64,
+        event: &Event,
+    ) -> io::Result<()> {
+        let entry = WalEntry {
+            seq: self.next_seq,
+            position,
+            line,
+            time,
+            event: event.clone(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    /// The sequence number of the last entry appended
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    /// Discard every entry, once they are covered by a checkpoint
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event::{Event, EventKind};
+    use crate::input::temp_path;
+    use crate::wal::{InputPosition, WriteAheadLog};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    fn event(tx: u32) -> Event {
+        Event {
+            kind: EventKind::Deposited,
+            client: 1,
+            tx,
+            amount: 1.5,
+        }
+    }
+
+    fn position(record: u64) -> InputPosition {
+        InputPosition {
+            byte: record * 10,
+            line: record + 1,
+            record,
+        }
+    }
+
+    #[test]
+    fn test_recover_entries() {
+        let path = &temp_path("test_recover_entries.wal");
+        let _ = fs::remove_file(path);
+        let (mut wal, entries) = WriteAheadLog::open(path, 0).expect("Failed to open wal");
+        assert!(entries.is_empty());
+        for tx in 1..=3 {
+            wal.append(position(tx as u64), tx as u64 + 1, 100, &event(tx))
+                .expect("Failed to append");
+        }
+        drop(wal);
+        // simulate a crash half way through writing an entry
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(b"{\"seq\":4,\"posi").unwrap();
+        drop(file);
+
+        let (mut wal, entries) = WriteAheadLog::open(path, 1).expect("Failed to reopen wal");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].seq, 2);
+        assert_eq!(entries[1].event, event(3));
+        assert_eq!(entries[1].position, position(3));
+        assert_eq!((entries[1].line, entries[1].time), (4, 100));
+        assert_eq!(wal.last_seq(), 3);
+        // the cut off entry is gone and new entries continue the sequence
+        wal.append(position(4), 5, 100, &event(4))
+            .expect("Failed to append");
+        let (wal, entries) = WriteAheadLog::open(path, 3).expect("Failed to reopen wal");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].seq, 4);
+        assert_eq!(wal.last_seq(), 4);
+    }
+}